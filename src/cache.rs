@@ -0,0 +1,484 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    process,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    loader::Source,
+    parser::{
+        column::{Bitmap, Column, Values},
+        intern::Symbol,
+        parse::DateTime,
+        table::{Field, Table},
+    },
+};
+
+/// First bytes of every cache file
+const MAGIC: &[u8; 8] = b"SPDXTBL\0";
+/// Bumped whenever the layout, or what loading puts in a table, changes
+const VERSION: u32 = 5;
+
+/// Directory in the user's cache directory the snapshots go in
+const CACHE_DIR: &str = "spotify_data_explorer";
+
+/// Tags of value vectors and of the fields of a mixed one. An empty vector
+/// is tagged like a null.
+const NULL: u8 = 0;
+const NUMBER: u8 = 1;
+const BOOL: u8 = 2;
+const DATE: u8 = 3;
+const STRING: u8 = 4;
+const SYMBOLS: u8 = 5;
+const MIXED: u8 = 6;
+
+/// 64 bit FNV-1a, which unlike `DefaultHasher` gives the same hash in every
+/// build, so it can name files and be kept in them.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Changes whenever a source is added, removed or touched. Files in a zip
+/// archive count their checksum too, which the archive has anyway.
+fn fingerprint(sources: &[Source], lenient: bool) -> io::Result<u64> {
+    let mut hasher = Fnv::default();
+    lenient.hash(&mut hasher);
+
+    for source in sources {
+        let (path, crc32) = match source {
+            Source::File(path) => (path, None),
+            Source::ZipEntry { archive, entry } => (archive, Some(entry.crc32())),
+        };
+        let meta = fs::metadata(path)?;
+        let modified = meta.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        (source.name(), meta.len(), modified, crc32).hash(&mut hasher);
+    }
+
+    Ok(hasher.finish())
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, n: u8) {
+        self.0.push(n);
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.0.extend(n.to_le_bytes());
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.0.extend(n.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.0.extend(s.as_bytes());
+    }
+
+    fn date(&mut self, d: &DateTime) {
+        self.0.extend(d.year.to_le_bytes());
+        self.0.extend([d.month, d.day, d.hour, d.minute, d.second]);
+    }
+
+    fn field(&mut self, field: &Field) {
+        match field {
+            Field::Null => self.u8(NULL),
+            Field::Number(n) => {
+                self.u8(NUMBER);
+                self.u64(*n);
+            }
+            Field::Bool(b) => {
+                self.u8(BOOL);
+                self.u8(*b as u8);
+            }
+            Field::Date(d) => {
+                self.u8(DATE);
+                self.date(d);
+            }
+            Field::String(s) => {
+                self.u8(STRING);
+                self.str(s);
+            }
+        }
+    }
+
+    /// Symbols are only good for this process, so every column keeps the
+    /// strings it has and refers to them by position.
+    fn symbols(&mut self, symbols: &[Symbol]) {
+        let mut index: HashMap<Symbol, u32> = HashMap::new();
+        let mut strings = Vec::new();
+        let ids: Vec<u32> = symbols
+            .iter()
+            .map(|s| {
+                *index.entry(*s).or_insert_with(|| {
+                    strings.push(s.as_str());
+                    strings.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        self.u32(strings.len() as u32);
+        strings.iter().for_each(|s| self.str(s));
+        ids.into_iter().for_each(|id| self.u32(id));
+    }
+
+    fn column(&mut self, column: &Column) {
+        self.u8(column.is_interned() as u8);
+        column.validity().words().iter().for_each(|w| self.u64(*w));
+
+        match column.values() {
+            Values::Empty => self.u8(NULL),
+            Values::Number(v) => {
+                self.u8(NUMBER);
+                v.iter().for_each(|n| self.u64(*n));
+            }
+            Values::Bool(v) => {
+                self.u8(BOOL);
+                v.iter().for_each(|b| self.u8(*b as u8));
+            }
+            Values::Date(v) => {
+                self.u8(DATE);
+                v.iter().for_each(|d| self.date(d));
+            }
+            Values::String(v) => {
+                self.u8(STRING);
+                v.iter().for_each(|s| self.str(s));
+            }
+            Values::Symbols(v) => {
+                self.u8(SYMBOLS);
+                self.symbols(v);
+            }
+            Values::Mixed(v) => {
+                self.u8(MIXED);
+                v.iter().for_each(|f| self.field(f));
+            }
+        }
+    }
+}
+
+/// The layout is `MAGIC`, `VERSION`, the fingerprint of the sources, the
+/// number of rows, the header and then every column: whether it's interned,
+/// its validity bitmap and its tagged values. Everything is little endian.
+fn encode(tbl: &Table, fingerprint: u64) -> Vec<u8> {
+    let mut out = Encoder(MAGIC.to_vec());
+    out.u32(VERSION);
+    out.u64(fingerprint);
+    out.u64(tbl.len() as u64);
+
+    out.u32(tbl.header.len() as u32);
+    for (name, col) in &tbl.header {
+        out.str(name);
+        out.u32(*col as u32);
+    }
+
+    out.u32(tbl.columns().len() as u32);
+    tbl.columns().iter().for_each(|c| out.column(c));
+
+    out.0
+}
+
+struct Bytes<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at.checked_add(n)?)?;
+        self.at += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+
+    fn date(&mut self) -> Option<DateTime> {
+        let year = u16::from_le_bytes(self.take(2)?.try_into().ok()?);
+        let [month, day, hour, minute, second] = self.take(5)?.try_into().ok()?;
+        Some(DateTime { day, month, year, minute, hour, second })
+    }
+
+    fn field(&mut self) -> Option<Field> {
+        Some(match self.u8()? {
+            NULL => Field::Null,
+            NUMBER => Field::Number(self.u64()?),
+            BOOL => Field::Bool(self.bool()?),
+            DATE => Field::Date(self.date()?),
+            STRING => Field::String(self.str()?.to_owned()),
+            _ => return None,
+        })
+    }
+
+    /// `n` of what `f` reads, `None` if any of them isn't there.
+    fn many<T, F: FnMut(&mut Self) -> Option<T>>(&mut self, n: usize, mut f: F) -> Option<Vec<T>> {
+        (0..n).map(|_| f(self)).collect()
+    }
+
+    fn column(&mut self, rows: usize) -> Option<Column> {
+        let interned = self.bool()?;
+        let valid = Bitmap::from_words(self.many(rows.div_ceil(64), Self::u64)?, rows)?;
+
+        let values = match self.u8()? {
+            NULL => Values::Empty,
+            NUMBER => Values::Number(self.many(rows, Self::u64)?),
+            BOOL => Values::Bool(self.many(rows, Self::bool)?),
+            DATE => Values::Date(self.many(rows, Self::date)?),
+            STRING => Values::String(self.many(rows, |b| b.str().map(str::to_owned))?),
+            SYMBOLS => {
+                let strings = self.u32()? as usize;
+                let symbols = self.many(strings, |b| b.str().map(Symbol::intern))?;
+                Values::Symbols(self.many(rows, |b| symbols.get(b.u32()? as usize).copied())?)
+            }
+            MIXED => Values::Mixed(self.many(rows, Self::field)?),
+            _ => return None,
+        };
+
+        Column::from_parts(values, valid, interned)
+    }
+}
+
+/// The table `encode` wrote, `None` unless it was written by this version
+/// from sources with `fingerprint` and is all there.
+fn decode(data: &[u8], fingerprint: u64) -> Option<Table> {
+    let mut bytes = Bytes { data, at: 0 };
+    if bytes.take(MAGIC.len())? != MAGIC || bytes.u32()? != VERSION || bytes.u64()? != fingerprint {
+        return None;
+    }
+
+    let rows = usize::try_from(bytes.u64()?).ok()?;
+
+    let header_len = bytes.u32()? as usize;
+    let header = bytes.many(header_len, |b| Some((b.str()?.to_owned(), b.u32()? as usize)))?;
+
+    let columns = bytes.u32()? as usize;
+    let columns = bytes.many(columns, |b| b.column(rows))?;
+
+    if bytes.at != data.len() {
+        return None;
+    }
+
+    Table::from_columns(header, columns).ok().filter(|tbl| tbl.len() == rows)
+}
+
+/// A snapshot of the table some exports load to, so later runs can skip
+/// parsing them. It's kept in the user's cache directory, one per data path,
+/// and only read back while the sources are as they were when it was written.
+pub struct Cache {
+    path: PathBuf,
+    fingerprint: u64,
+}
+
+impl Cache {
+    /// The cache for loading `sources` from `data_path`, `None` when there is
+    /// nowhere to keep one or the sources can't be looked at. It goes in
+    /// `$XDG_CACHE_HOME`, `%LOCALAPPDATA%` or `~/.cache`, the first one set.
+    pub fn locate(data_path: &Path, sources: &[Source], lenient: bool) -> Option<Cache> {
+        let var = |name| env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+        // `LOCALAPPDATA` is where Windows keeps caches, it has no `HOME`
+        let dir = var("XDG_CACHE_HOME").or_else(|| var("LOCALAPPDATA")).or_else(|| var("HOME").map(|home| home.join(".cache")))?;
+
+        let mut hasher = Fnv::default();
+        (fs::canonicalize(data_path).ok()?, lenient).hash(&mut hasher);
+        let path = dir.join(CACHE_DIR).join(format!("{:016x}.bin", hasher.finish()));
+
+        Some(Cache { path, fingerprint: fingerprint(sources, lenient).ok()? })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The stored table, `None` when there is none or it's stale.
+    pub fn read(&self) -> Option<Table> {
+        decode(&fs::read(&self.path).ok()?, self.fingerprint)
+    }
+
+    /// Stores `tbl`. It's written next to the old snapshot and moved over it,
+    /// so another run never reads half of one.
+    pub fn write(&self, tbl: &Table) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let partial = self.path.with_extension(format!("{}.tmp", process::id()));
+        let written = fs::write(&partial, encode(tbl, self.fingerprint)).and_then(|_| fs::rename(&partial, &self.path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::discriminant;
+
+    use super::*;
+
+    /// A table with a column of every kind, nulls in all of them and more
+    /// rows than one bitmap word holds.
+    fn table() -> Table {
+        let mut tbl = Table::new(["artist", "ms_played", "shuffle", "ts", "reason", "mixed", "nothing"]);
+
+        for i in 0..70u64 {
+            let null = i % 7 == 3;
+            let ts = DateTime { day: 1 + (i % 28) as u8, month: 12, year: 2020, minute: i as u8 % 60, hour: 23, second: 59 };
+
+            let mixed = match i % 3 {
+                0 => Field::Number(i),
+                1 => Field::String(format!("row {i}")),
+                _ => Field::Null,
+            };
+            let row = [
+                (!null).then(|| format!("artist {}", i % 5)).into(),
+                (!null).then_some(i * 1000).into(),
+                (!null).then_some(i % 2 == 0).into(),
+                (!null).then_some(ts).into(),
+                (!null).then(|| format!("«{i}»")).into(),
+                mixed,
+                Field::Null,
+            ];
+            tbl.insert(row).unwrap();
+        }
+
+        tbl
+    }
+
+    #[test]
+    fn round_trip() {
+        let tbl = table();
+        assert!(matches!(tbl.column(0).values(), Values::Symbols(_)));
+        assert!(matches!(tbl.column(5).values(), Values::Mixed(_)));
+
+        let read = decode(&encode(&tbl, 42), 42).unwrap();
+        assert_eq!(read.header, tbl.header);
+        assert_eq!(read.len(), tbl.len());
+        for (a, b) in read.columns().iter().zip(tbl.columns()) {
+            assert_eq!(a.is_interned(), b.is_interned());
+            assert_eq!(discriminant(a.values()), discriminant(b.values()));
+        }
+        for row in 0..tbl.len() {
+            for col in 0..tbl.columns().len() {
+                assert_eq!(read.get(row, col), tbl.get(row, col));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_table() {
+        let tbl = Table::new(["artist", "ms_played"]);
+        let read = decode(&encode(&tbl, 0), 0).unwrap();
+
+        assert_eq!(read.header, tbl.header);
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn rejects_other_sources_and_damage() {
+        let data = encode(&table(), 42);
+
+        assert!(decode(&data, 43).is_none());
+        assert!(decode(&data[..data.len() - 1], 42).is_none());
+        assert!(decode(&[data.as_slice(), &[0]].concat(), 42).is_none());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut data = encode(&table(), 42);
+        let at = MAGIC.len()..MAGIC.len() + 4;
+        assert_eq!(data[at.clone()], VERSION.to_le_bytes());
+
+        for version in [0, VERSION - 1, VERSION + 1] {
+            data[at.clone()].copy_from_slice(&version.to_le_bytes());
+            assert!(decode(&data, 42).is_none(), "{version}");
+        }
+    }
+
+    /// A file of its own in the temp directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &[u8]) -> Self {
+            let path = env::temp_dir().join(format!("{}-{}-{name}", CACHE_DIR, process::id()));
+            fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn changed_sources_miss() {
+        let export = TempFile::new("export.json", b"[]");
+        let snapshot = TempFile::new("snapshot.bin", b"");
+        let sources = [Source::File(export.0.clone())];
+
+        let cache = |lenient| Cache { path: snapshot.0.clone(), fingerprint: fingerprint(&sources, lenient).unwrap() };
+        let written = cache(false);
+        written.write(&table()).unwrap();
+        assert_eq!(written.read().map(|tbl| tbl.len()), Some(70));
+        assert!(cache(true).read().is_none());
+
+        // a different length
+        fs::write(&export.0, b"[ ]").unwrap();
+        assert_ne!(cache(false).fingerprint, written.fingerprint);
+        assert!(cache(false).read().is_none());
+
+        // the same length, touched
+        fs::write(&export.0, b"[]").unwrap();
+        let file = fs::File::options().write(true).open(&export.0).unwrap();
+        let touched = file.metadata().unwrap().modified().unwrap() + std::time::Duration::from_secs(60);
+        file.set_modified(touched).unwrap();
+        assert_ne!(cache(false).fingerprint, written.fingerprint);
+        assert!(cache(false).read().is_none());
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+    num::NonZeroUsize,
+    path::PathBuf,
+    thread,
+};
+
+use crate::{
+    error::{Error, ErrorKind, Result},
+    parser::{format::FieldFormat, tz::TimeZone},
+};
+
+pub const USAGE: &str = "\
+usage: spotify_data_explorer <command> [options]
+
+commands:
+    load <dir|zip>        parse the exports and report what was found
+    query <query>         run a query, e.g. \"SELECT song, artist WHERE artist = 'x' AND msplayed > 30000 ORDER BY artist, time DESC LIMIT 20\"
+                          or \"SELECT artist, sum(msplayed) AS total GROUP BY artist ORDER BY total DESC\"
+                          or \"SELECT year(time), month(time), count(*) GROUP BY year(time), month(time)\"
+    top <column>          the most common values of <column>
+    search <column> <text>
+                          values of <column> most like <text>, for when a query finds nothing
+    stats                 totals over the whole history
+    export [query]        write the history, or what [query] gives, to --output or stdout
+    help                  show this message
+
+options:
+    --data <dir|zip|csv>  where the exports are, or a csv written by export, defaults to $SPOTIFY_DATA or ./data
+    --format <format>     output format: table, the default on a terminal, text, csv, json or ndjson
+    --limit <n>           print at most <n> rows
+    --columns <a,b,..>    only print these columns
+    --output <file>       write to <file> instead of stdout
+    --lenient             skip bad records instead of failing
+    --threads <n>         parse on at most <n> threads, defaults to one per core
+    --no-cache            parse the exports even if they haven't changed, and don't keep what they gave
+    --tz <zone>           show times in <zone>, an offset like +02:00 or a name like Europe/Stockholm
+    --borders             draw lines around table cells
+    --no-color            don't colour the table, also off when $NO_COLOR is set
+    --no-pager            don't page long tables through $PAGER
+    --display <c=f,..>    show column <c> as <f>: duration, number, percent, raw, or a date pattern like %Y-%m-%d;
+                          tables show play time as durations and counts with separators unless told otherwise
+    --date-format <p>     show dates by pattern <p>, e.g. \"%a %d %b %Y %H:%M\"";
+
+/// Environment variable holding the default data path.
+pub const DATA_ENV: &str = "SPOTIFY_DATA";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Load,
+    Query(String),
+    Top(String),
+    /// column and the text to look for
+    Search(String, String),
+    Stats,
+    Export(Option<String>),
+    Help,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// fields joined by `|`, for scripts
+    Text,
+    /// aligned columns, see `render::render`
+    Table,
+    /// see `Table::write_csv`
+    Csv,
+    /// see `Table::write_json`
+    Json,
+    /// see `Table::write_ndjson`
+    Ndjson,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "table" => Some(OutputFormat::Table),
+            "csv" => Some(OutputFormat::Csv),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Args {
+    pub command: Command,
+    pub data: PathBuf,
+    pub format: OutputFormat,
+    pub limit: Option<usize>,
+    pub columns: Option<Vec<String>>,
+    pub output: Option<PathBuf>,
+    pub lenient: bool,
+    /// read and write the snapshot of the parsed exports, see `cache::Cache`
+    pub cache: bool,
+    /// most threads parsing runs on
+    pub threads: usize,
+    /// `None` leaves times in UTC
+    pub tz: Option<TimeZone>,
+    pub borders: bool,
+    pub color: bool,
+    /// page long tables through `$PAGER`
+    pub pager: bool,
+    /// column and how to show it, later ones win
+    pub display: Vec<(String, FieldFormat)>,
+    /// pattern for dates no `display` entry covers, see `format::format_date`
+    pub date_format: Option<String>,
+}
+
+fn usage(msg: String) -> Error {
+    Error::new(ErrorKind::Usage(msg))
+}
+
+impl Args {
+    pub fn from_env() -> Result<Self> {
+        Args::parse(env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut args = args.into_iter();
+        let mut positional = Vec::new();
+
+        let mut data = None;
+        let mut format = None;
+        let mut limit = None;
+        let mut columns = None;
+        let mut output = None;
+        let mut lenient = false;
+        let mut cache = true;
+        let mut threads = None;
+        let mut tz = None;
+        let mut borders = false;
+        let mut color = true;
+        let mut pager = true;
+        let mut display = Vec::new();
+        let mut date_format = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| usage(format!("{name} expects a value")));
+
+            match arg.as_str() {
+                "--data" => data = Some(PathBuf::from(value("--data")?)),
+                "--format" => {
+                    let name = value("--format")?;
+                    format = Some(OutputFormat::from_name(&name).ok_or_else(|| usage(format!("unknown format '{name}'")))?);
+                }
+                "--limit" => {
+                    let n = value("--limit")?;
+                    limit = Some(n.parse().map_err(|_| usage(format!("--limit expects a number, got '{n}'")))?);
+                }
+                "--columns" => columns = Some(value("--columns")?.split(',').map(|c| c.trim().to_owned()).collect()),
+                "--output" => output = Some(PathBuf::from(value("--output")?)),
+                "--lenient" => lenient = true,
+                "--no-cache" => cache = false,
+                "--threads" => {
+                    let n = value("--threads")?;
+                    threads = Some(n.parse().ok().filter(|n| *n > 0).ok_or_else(|| usage(format!("--threads expects a positive number, got '{n}'")))?);
+                }
+                "--tz" => tz = Some(TimeZone::from_name(&value("--tz")?).map_err(|e| usage(e.to_string()))?),
+                "--borders" => borders = true,
+                "--no-color" => color = false,
+                "--no-pager" => pager = false,
+                "--display" => {
+                    for entry in value("--display")?.split(',') {
+                        let (column, name) = entry.split_once('=').ok_or_else(|| usage(format!("--display expects column=format, got '{entry}'")))?;
+                        let format = FieldFormat::from_name(name.trim()).ok_or_else(|| usage(format!("unknown display format '{}'", name.trim())))?;
+                        display.push((column.trim().to_owned(), format));
+                    }
+                }
+                "--date-format" => date_format = Some(value("--date-format")?),
+                "-h" | "--help" => positional.insert(0, "help".to_owned()),
+                flag if flag.starts_with("--") => return Err(usage(format!("unknown option '{flag}'"))),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let name = positional.next().unwrap_or_else(|| "help".to_owned());
+        let rest: Vec<String> = positional.collect();
+        // the rest is one query, so quoting it as a whole is optional
+        let query = (!rest.is_empty()).then(|| rest.join(" "));
+
+        let command = match name.as_str() {
+            "load" => {
+                if let Some(path) = rest.first() {
+                    data = Some(PathBuf::from(path));
+                }
+                Command::Load
+            }
+            "query" => Command::Query(query.ok_or_else(|| usage("query expects a query".to_owned()))?),
+            "top" => Command::Top(rest.first().cloned().ok_or_else(|| usage("top expects a column".to_owned()))?),
+            "search" => match rest.split_first() {
+                Some((column, text)) if !text.is_empty() => Command::Search(column.clone(), text.join(" ")),
+                _ => return Err(usage("search expects a column and some text".to_owned())),
+            },
+            "stats" => Command::Stats,
+            "export" => Command::Export(query),
+            "help" => Command::Help,
+            other => return Err(usage(format!("unknown command '{other}'"))),
+        };
+
+        let threads = threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get));
+
+        let data = data
+            .or_else(|| env::var_os(DATA_ENV).map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("data"));
+
+        // people get the aligned table, pipes and files the plain one
+        let terminal = output.is_none() && io::stdout().is_terminal();
+        let format = format.unwrap_or(if terminal { OutputFormat::Table } else { OutputFormat::Text });
+        let color = color && terminal && env::var_os("NO_COLOR").is_none();
+
+        Ok(Args {
+            command,
+            data,
+            format,
+            limit,
+            columns,
+            output,
+            lenient,
+            cache,
+            threads,
+            tz,
+            borders,
+            color,
+            pager: pager && terminal,
+            display,
+            date_format,
+        })
+    }
+}
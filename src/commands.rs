@@ -0,0 +1,200 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    cli::{Args, Command, OutputFormat, USAGE},
+    error::Result,
+    parser::{
+        aggregate::Aggregate,
+        format::{self, format_duration, format_number},
+        parse::DateTime,
+        table::{Field, SortKey, Table},
+    },
+    query,
+    render::{self, Style},
+};
+
+/// rows `top` prints when no `--limit` is given
+const DEFAULT_TOP: usize = 10;
+
+/// "did you mean" candidates shown for a value no row has
+const SUGGESTIONS: usize = 3;
+
+fn write_table(args: &Args, mut tbl: Table) -> Result<()> {
+    if let Some(columns) = &args.columns {
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        for col in &columns {
+            tbl.get_col(col)?;
+        }
+        tbl = tbl.select(&columns);
+    }
+
+    // scripts get raw values unless they ask, people get the friendly ones.
+    // before the limit so percentages are of everything, not what's shown
+    let guess = args.format == OutputFormat::Table;
+    let formats = format::column_formats(&tbl, &args.display, args.date_format.as_deref(), guess);
+
+    if let Some(limit) = args.limit {
+        tbl = tbl.limit(limit);
+    }
+
+    // files get the whole width, only a terminal needs fitting
+    let size = match (args.format, &args.output) {
+        (OutputFormat::Table, None) => render::terminal_size(),
+        _ => None,
+    };
+
+    // header and its underline, plus borders
+    let lines = tbl.len() + if args.borders { 4 } else { 2 };
+    let mut pager = match (args.format, args.pager, size) {
+        (OutputFormat::Table, true, Some((_, height))) if lines >= height => render::pager(),
+        _ => None,
+    };
+
+    let mut out: Box<dyn Write> = match (&args.output, pager.as_mut().and_then(|p| p.stdin.take())) {
+        (Some(path), _) => Box::new(BufWriter::new(File::create(path)?)),
+        (None, Some(stdin)) => Box::new(BufWriter::new(stdin)),
+        (None, None) => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let written = match args.format {
+        OutputFormat::Text => {
+            let header: Vec<&str> = tbl.header.iter().map(|(name, _)| name.as_str()).collect();
+            writeln!(out, "{}", header.join("|")).and_then(|_| {
+                (0..tbl.len()).try_for_each(|row| {
+                    let fields: Vec<String> = tbl.header.iter().zip(&formats).map(|((_, col), f)| tbl.get(row, *col).format(f)).collect();
+                    writeln!(out, "{}", fields.join("|"))
+                })
+            })
+        }
+        OutputFormat::Table => {
+            let style = Style { borders: args.borders, color: args.color, max_width: size.map(|(width, _)| width) };
+            render::render(&tbl, &formats, &style, &mut out)
+        }
+        OutputFormat::Csv => tbl.write_csv(&mut out, args.tz.as_ref()),
+        OutputFormat::Json => tbl.write_json(&mut out, args.tz.as_ref()),
+        OutputFormat::Ndjson => tbl.write_ndjson(&mut out, args.tz.as_ref()),
+    };
+
+    let flushed = written.and_then(|_| out.flush());
+    drop(out);
+
+    match pager {
+        Some(mut pager) => {
+            pager.wait()?;
+            // quitting the pager early closes the pipe, which isn't an error
+            match flushed {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                other => Ok(other?),
+            }
+        }
+        None => Ok(flushed?),
+    }
+}
+
+/// Runs `q`, and when nothing matches points out values of `col = '..'`
+/// that no row has along with what they were probably meant to be.
+fn run_query(args: &Args, q: &str, tbl: Table) -> Result<()> {
+    let plan = query::compile(q, &tbl)?;
+
+    // the query takes the table, so the column of a value no row has is kept
+    // for suggesting what it was meant to be, should nothing match
+    let mut missing = Vec::new();
+    for (column, value) in plan.exact_matches() {
+        let col = tbl.column(tbl.get_col(column)?);
+        if !col.has_text(value) {
+            missing.push((column, value, col.clone()));
+        }
+    }
+
+    let result = plan.execute(tbl)?;
+
+    if result.is_empty() {
+        for (column, value, col) in &missing {
+            let candidates = col.fuzzy_find(value, SUGGESTIONS);
+            if !candidates.is_empty() {
+                let candidates: Vec<String> = candidates.iter().map(|(c, _)| format!("'{c}'")).collect();
+                eprintln!("no {column} is '{value}', did you mean {}?", candidates.join(" or "));
+            }
+        }
+    }
+
+    write_table(args, result)
+}
+
+fn search(args: &Args, tbl: &Table, column: &str, text: &str) -> Result<()> {
+    let found = tbl.fuzzy_find(column, text, args.limit.unwrap_or(DEFAULT_TOP))?;
+
+    let mut res = Table::new([column, "score"]);
+    for (value, score) in found {
+        res.insert([Field::String(value), Field::Number((score * 100.0).round() as u64)])?;
+    }
+
+    write_table(&Args { limit: None, ..args.clone() }, res)
+}
+
+/// Number of different non-null values in `column`.
+fn distinct(tbl: &Table, column: &str) -> Result<usize> {
+    let distinct = tbl.group_by(&[], &[Aggregate::CountDistinct(column.to_owned())])?;
+    Ok(match distinct.get(0, 0) {
+        Field::Number(n) => n as usize,
+        _ => 0,
+    })
+}
+
+fn stats(args: &Args, tbl: &Table) -> Result<()> {
+    let time = tbl.get_col("time")?;
+    let msplayed = tbl.get_col("msplayed")?;
+
+    let total_ms: u64 = (0..tbl.len()).map(|row| match tbl.get(row, msplayed) {
+        Field::Number(n) => n,
+        _ => 0,
+    }).sum();
+
+    let dates: Vec<DateTime> = (0..tbl.len()).filter_map(|row| match tbl.get(row, time) {
+        Field::Date(d) => Some(d),
+        _ => None,
+    }).collect();
+    let first = dates.iter().reduce(|a, b| if b < a { b } else { a });
+    let last = dates.iter().reduce(|a, b| if b > a { b } else { a });
+
+    let date = |d: &DateTime| match &args.date_format {
+        Some(pattern) => format::format_date(d, pattern),
+        None => d.to_string(),
+    };
+
+    println!("plays:            {}", format_number(tbl.len() as u64));
+    println!("listening time:   {}", format_duration(total_ms));
+    println!("distinct artists: {}", format_number(distinct(tbl, "artist")? as u64));
+    println!("distinct songs:   {}", format_number(distinct(tbl, "song")? as u64));
+
+    if let (Some(first), Some(last)) = (first, last) {
+        println!("first play:       {}", date(first));
+        println!("last play:        {}", date(last));
+    }
+
+    Ok(())
+}
+
+/// Runs everything but `load` and `help`, which need no table or none at all.
+pub fn run(args: &Args, tbl: Table) -> Result<()> {
+    match &args.command {
+        Command::Load | Command::Help => Ok(()),
+        Command::Query(q) => run_query(args, q, tbl),
+        Command::Top(column) => {
+            let grouped = tbl.is_not_null(column)?.group_by(&[column], &[Aggregate::Count])?.sort_by(&[SortKey::desc("count")])?;
+            let limit = args.limit.unwrap_or(DEFAULT_TOP);
+            write_table(&Args { limit: Some(limit), ..args.clone() }, grouped)
+        }
+        Command::Search(column, text) => search(args, &tbl, column, text),
+        Command::Stats => stats(args, &tbl),
+        Command::Export(Some(q)) => run_query(args, q, tbl),
+        Command::Export(None) => write_table(args, tbl),
+    }
+}
+
+pub fn print_usage() {
+    println!("{}", USAGE);
+}
@@ -0,0 +1,120 @@
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    io,
+};
+
+use crate::{
+    parser::{
+        csv::CsvError,
+        parse::{DateTimeError, DebugInfo},
+        parse_arguments,
+        table::DataErrors,
+    },
+    query::QueryError,
+};
+
+pub enum ErrorKind {
+    Io(io::Error),
+    Json(parse_arguments::Error),
+    Csv(CsvError),
+    DateTime(DateTimeError),
+    Data(DataErrors),
+    Query(QueryError),
+    /// bad command line
+    Usage(String),
+}
+
+/// The crate wide error. `debug` points at the place in the export the error
+/// came from, when there is one.
+pub struct Error {
+    pub kind: ErrorKind,
+    pub debug: Option<Box<DebugInfo>>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new<K: Into<ErrorKind>>(kind: K) -> Self {
+        Error { kind: kind.into(), debug: None }
+    }
+
+    pub fn with_debug(mut self, debug: DebugInfo) -> Self {
+        self.debug = Some(Box::new(debug));
+        self
+    }
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(value: io::Error) -> Self {
+        ErrorKind::Io(value)
+    }
+}
+
+impl From<parse_arguments::Error> for ErrorKind {
+    fn from(value: parse_arguments::Error) -> Self {
+        match value {
+            parse_arguments::Error::Io(e) => ErrorKind::Io(e),
+            other => ErrorKind::Json(other),
+        }
+    }
+}
+
+impl From<CsvError> for ErrorKind {
+    fn from(value: CsvError) -> Self {
+        ErrorKind::Csv(value)
+    }
+}
+
+impl From<DateTimeError> for ErrorKind {
+    fn from(value: DateTimeError) -> Self {
+        ErrorKind::DateTime(value)
+    }
+}
+
+impl From<DataErrors> for ErrorKind {
+    fn from(value: DataErrors) -> Self {
+        ErrorKind::Data(value)
+    }
+}
+
+impl From<QueryError> for ErrorKind {
+    fn from(value: QueryError) -> Self {
+        ErrorKind::Query(value)
+    }
+}
+
+impl<T: Into<ErrorKind>> From<T> for Error {
+    fn from(value: T) -> Self {
+        Error::new(value)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Io(e) => write!(f, "{}", e),
+            ErrorKind::Json(e) => write!(f, "invalid json: {}", e),
+            ErrorKind::Csv(e) => write!(f, "invalid csv: {}", e),
+            ErrorKind::DateTime(e) => write!(f, "invalid date: {}", e),
+            ErrorKind::Data(e) => write!(f, "{}", e),
+            ErrorKind::Query(e) => write!(f, "{}", e),
+            ErrorKind::Usage(msg) => write!(f, "{}\n\n{}", msg, crate::cli::USAGE),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(debug) = &self.debug {
+            write!(f, "{}: ", debug)?;
+        }
+
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fs::{self, read_dir, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Result,
+    parser::{
+        csv::CsvBuilder,
+        detect::ExportFormat,
+        parse::{parse_chunk, parse_reader, ParseReport},
+        parse_arguments::{split_array, Position},
+        table::{Table, BIG_HISTORY_TABLE},
+    },
+    zip::{ZipArchive, ZipEntry},
+};
+
+/// Sources over twice this many bytes are cut into chunks of about this size,
+/// so one big export doesn't keep a single thread busy while the rest wait
+const CHUNK_BYTES: usize = 4 << 20;
+
+/// One export file, either on disk or inside a zip archive.
+#[derive(Clone, Debug)]
+pub enum Source {
+    File(PathBuf),
+    ZipEntry { archive: PathBuf, entry: ZipEntry },
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+pub fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) && path.is_file()
+}
+
+/// A table written earlier by `export --format csv`.
+pub fn load_csv(path: &Path) -> Result<Table> {
+    let file_name = path.file_name().unwrap_or_default().to_owned();
+    CsvBuilder::new().read(BufReader::new(File::open(path)?), file_name)
+}
+
+impl Source {
+    /// Where the source lives, for messages.
+    pub fn name(&self) -> String {
+        match self {
+            Source::File(path) => path.display().to_string(),
+            Source::ZipEntry { archive, entry } => format!("{}/{}", archive.display(), entry.name),
+        }
+    }
+
+    pub fn file_name(&self) -> OsString {
+        match self {
+            Source::File(path) => path.file_name().unwrap_or_default().to_owned(),
+            Source::ZipEntry { entry, .. } => entry.file_name().into(),
+        }
+    }
+
+    /// Bytes the export takes up once uncompressed.
+    pub fn size(&self) -> Result<u64> {
+        Ok(match self {
+            Source::File(path) => fs::metadata(path)?.len(),
+            Source::ZipEntry { entry, .. } => entry.size,
+        })
+    }
+
+    pub fn open(&self) -> Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Source::File(path) => Box::new(BufReader::new(File::open(path)?)),
+            Source::ZipEntry { archive, entry } => Box::new(BufReader::new(entry.reader(File::open(archive)?)?)),
+        })
+    }
+
+    /// Like `open`, for only the bytes in `range`. Only files can be read from
+    /// the middle, entries of an archive would have to be inflated up to there.
+    pub fn open_range(&self, range: Range<usize>) -> Result<Box<dyn Read + Send>> {
+        match self {
+            Source::File(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(range.start as u64))?;
+                Ok(Box::new(BufReader::new(file.take((range.end - range.start) as u64))))
+            }
+            Source::ZipEntry { .. } => Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} can only be read whole", self.name())).into()),
+        }
+    }
+
+    /// Looks at the file name first and falls back to sniffing the first record.
+    pub fn detect(&self) -> Result<Option<ExportFormat>> {
+        let name = self.file_name().to_string_lossy().into_owned();
+
+        if let Some(format) = ExportFormat::from_name(&name) {
+            return Ok(Some(format));
+        }
+
+        if !name.ends_with(".json") {
+            return Ok(None);
+        }
+
+        ExportFormat::from_content(self.open()?)
+    }
+
+    pub fn parse(&self, format: ExportFormat, lenient: bool) -> Result<(Table, ParseReport)> {
+        self.parse_from(self.open()?, None, format, lenient)
+    }
+
+    /// Like `parse`, for what of the export `reader` gives. `start` is where
+    /// in the export that is, when `split_array` cut it out.
+    fn parse_from<R: Read>(&self, reader: R, start: Option<Position>, format: ExportFormat, lenient: bool) -> Result<(Table, ParseReport)> {
+        let mut tbl = Table::new(BIG_HISTORY_TABLE);
+        let mut builder = format.builder(&mut tbl);
+
+        let report = match start {
+            Some(start) => parse_chunk(reader, start, self.file_name(), builder.as_mut(), lenient)?,
+            None => parse_reader(reader, self.file_name(), builder.as_mut(), lenient)?,
+        };
+        drop(builder);
+
+        Ok((tbl, report))
+    }
+}
+
+/// Work for the threads of `parse_parallel`.
+enum Job {
+    /// the source at this index, parsed whole or cut into chunks
+    Source(usize),
+    /// run `index` of the source at `source`, the bytes in `range`
+    Chunk { source: usize, index: usize, range: Range<usize>, start: Position },
+}
+
+/// What running a job gave.
+enum Step {
+    Parsed(Table, ParseReport),
+    /// more jobs, one per chunk
+    Split(Vec<Job>),
+}
+
+/// Jobs not yet taken. Jobs being worked on are counted as they can add more,
+/// so a thread finding nothing left only stops once none are.
+#[derive(Default)]
+struct Queue {
+    jobs: VecDeque<Job>,
+    running: usize,
+    /// a job failed, so the rest can be skipped
+    failed: bool,
+}
+
+/// A parsed part of a source: its index, which chunk it was and how long it took.
+type Done = (usize, usize, Result<(Table, ParseReport, Duration)>);
+
+/// What every job is run with.
+struct Context<'a> {
+    sources: &'a [(Source, ExportFormat)],
+    lenient: bool,
+}
+
+/// Parses the source at `i` if it's small, otherwise finds where its records
+/// are and leaves parsing them to other jobs.
+fn parse_or_split(i: usize, cx: &Context) -> Result<Step> {
+    let (source, format) = &cx.sources[i];
+    let size = source.size()?;
+
+    // entries of an archive would have to be inflated from the start for every
+    // chunk, so they're parsed whole and only files are split
+    if size > 2 * CHUNK_BYTES as u64 && matches!(source, Source::File(_)) {
+        if let Some(runs) = split_array(source.open()?, CHUNK_BYTES)?.filter(|runs| runs.len() > 1) {
+            let jobs = runs
+                .into_iter()
+                .enumerate()
+                .map(|(index, (range, start))| Job::Chunk { source: i, index, range, start })
+                .collect();
+            return Ok(Step::Split(jobs));
+        }
+        // a lone record, or something broken which parsing it whole points at best
+    }
+
+    let (tbl, report) = source.parse(*format, cx.lenient)?;
+    Ok(Step::Parsed(tbl, report))
+}
+
+fn run(job: Job, cx: &Context) -> Result<Step> {
+    match job {
+        Job::Source(i) => parse_or_split(i, cx),
+        Job::Chunk { source, range, start, .. } => {
+            let (source, format) = &cx.sources[source];
+            let (tbl, report) = source.parse_from(source.open_range(range)?, Some(start), *format, cx.lenient)?;
+            Ok(Step::Parsed(tbl, report))
+        }
+    }
+}
+
+/// Takes jobs off `queue` until there are none left or one fails.
+fn work(queue: &(Mutex<Queue>, Condvar), cx: &Context) -> Vec<Done> {
+    let (lock, changed) = queue;
+    let mut done = Vec::new();
+
+    loop {
+        let job = {
+            let mut queue = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if queue.failed {
+                    return done;
+                }
+                if let Some(job) = queue.jobs.pop_front() {
+                    queue.running += 1;
+                    break job;
+                }
+                if queue.running == 0 {
+                    return done;
+                }
+                queue = changed.wait(queue).unwrap_or_else(PoisonError::into_inner);
+            }
+        };
+
+        let (source, index) = match &job {
+            Job::Source(i) => (*i, 0),
+            Job::Chunk { source, index, .. } => (*source, *index),
+        };
+
+        let start = Instant::now();
+        let step = run(job, cx);
+
+        let mut queue = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.running -= 1;
+        match step {
+            Ok(Step::Split(jobs)) => queue.jobs.extend(jobs),
+            Ok(Step::Parsed(tbl, report)) => done.push((source, index, Ok((tbl, report, start.elapsed())))),
+            Err(e) => {
+                queue.failed = true;
+                done.push((source, index, Err(e)));
+            }
+        }
+        drop(queue);
+        changed.notify_all();
+    }
+}
+
+/// A source `parse_parallel` parsed, with its chunks put back together.
+pub struct Parsed {
+    pub source: Source,
+    pub table: Table,
+    pub report: ParseReport,
+    /// how many parts it was parsed in
+    pub chunks: usize,
+    /// spent parsing it, summed over its chunks
+    pub took: Duration,
+}
+
+/// Parses `sources` on up to `threads` threads. Big files are cut into
+/// chunks at record boundaries, which are parsed like sources of their own.
+/// Whichever thread gets to what, sources and their rows come back in the
+/// order given, and the error is that of the first source that failed.
+pub fn parse_parallel(sources: Vec<(Source, ExportFormat)>, threads: usize, lenient: bool) -> Result<Vec<Parsed>> {
+    let queue = (Mutex::new(Queue { jobs: (0..sources.len()).map(Job::Source).collect(), ..Queue::default() }), Condvar::new());
+    let cx = Context { sources: &sources, lenient };
+
+    let mut done: Vec<Done> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1)).map(|_| scope.spawn(|| work(&queue, &cx))).collect();
+        workers.into_iter().flat_map(|w| w.join().expect("parsing thread panicked")).collect()
+    });
+    done.sort_by_key(|(source, index, _)| (*source, *index));
+
+    let mut done = done.into_iter().peekable();
+    let mut parsed = Vec::with_capacity(sources.len());
+
+    for (i, (source, _)) in sources.into_iter().enumerate() {
+        let mut part = Parsed { source, table: Table::new(BIG_HISTORY_TABLE), report: ParseReport::default(), chunks: 0, took: Duration::ZERO };
+
+        while let Some((_, _, result)) = done.next_if(|(source, ..)| *source == i) {
+            let (tbl, report, took) = result?;
+
+            part.table.append(tbl)?;
+            part.report.records += report.records;
+            part.report.rejected.extend(report.rejected);
+            for (key, count) in report.unknown_keys {
+                *part.report.unknown_keys.entry(key).or_default() += count;
+            }
+            part.chunks += 1;
+            part.took += took;
+        }
+
+        parsed.push(part);
+    }
+
+    Ok(parsed)
+}
+
+fn zip_sources(archive: &Path) -> Result<Vec<Source>> {
+    let zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+
+    Ok(zip
+        .entries()
+        .iter()
+        .filter(|entry| !entry.is_dir() && entry.name.ends_with(".json"))
+        .map(|entry| Source::ZipEntry { archive: archive.to_owned(), entry: entry.clone() })
+        .collect())
+}
+
+/// Everything loadable under `path`: a single export, a zip archive as Spotify
+/// sends it, or a directory holding either.
+pub fn sources(path: &Path) -> Result<Vec<Source>> {
+    if is_zip(path) {
+        return zip_sources(path);
+    }
+
+    if !path.is_dir() {
+        return Ok(vec![Source::File(path.to_owned())]);
+    }
+
+    let mut paths: Vec<PathBuf> = read_dir(path)?
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.is_file())
+        .collect();
+    paths.sort();
+
+    let mut res = Vec::new();
+    for path in paths {
+        if is_zip(&path) {
+            res.extend(zip_sources(&path)?);
+        } else {
+            res.push(Source::File(path));
+        }
+    }
+
+    Ok(res)
+}
@@ -1,100 +1,125 @@
-use parser::{parse::{parse, BigBuilder}, table::{Table, BIG_HISTORY_TABLE}, utils::quick_date};
-use std::{fs::read_dir, io::Error, path::PathBuf, thread, time::{Duration, Instant}};
-
+use cache::Cache;
+use cli::{Args, Command};
+use commands::{print_usage, run};
+use error::{Error, Result};
+use loader::{is_csv, load_csv, parse_parallel, sources};
+use parser::table::{SortKey, Table, BIG_HISTORY_TABLE};
+use std::{path::Path, time::Instant};
+
+pub mod cache;
+pub mod cli;
+pub mod commands;
+pub mod error;
+pub mod loader;
 pub mod parser;
+pub mod query;
+pub mod render;
+pub mod zip;
+
+/// How many rejected records are printed before the summary is cut short
+const MAX_REJECTS_SHOWN: usize = 10;
+
+fn load(data_path: &Path, lenient: bool, use_cache: bool, threads: usize) -> Result<Table> {
+    if is_csv(data_path) {
+        let start = Instant::now();
+        let tbl = load_csv(data_path)?;
+        eprintln!("Loaded {} rows from {}: {:.2?}", tbl.len(), data_path.display(), start.elapsed());
+        return Ok(tbl);
+    }
 
-fn get_paths(dir: &str) -> Result<Vec<PathBuf>, Error> {
-    Ok(read_dir(dir)?
-        .filter_map(|x| x.ok())
-        .map(|x| x.path())
-        .collect())
-}
-
-fn parse_file(file: PathBuf) -> (u8, Table, Duration) {
-    let start_read_files = Instant::now();
-    let mut tbl = Table::new(BIG_HISTORY_TABLE);
-    let mut builder = BigBuilder::new(&mut tbl);
-
-    parse(file.clone(), &mut builder).unwrap();
-
-    let filename = file.file_name().unwrap().to_str().unwrap();
-    let parts: Vec<&str> = filename.split(&['_', '.'][..]).collect();
-    let number =  parts.get(parts.len() - 2).unwrap();
-
-    println!("{number}");
-
-    (number.parse().unwrap(), tbl, start_read_files.elapsed())
-}
-
-fn main() {
     let start_read_files = Instant::now();
 
-    println!("getting file paths...");
+    eprintln!("getting file paths...");
 
-    let paths = get_paths(r"X:\OneDrive\programmering\rs\spotify_data_explorer\data\full")
-        .expect("Could not get files");
+    let paths = sources(data_path)?;
     let elapsed_read_files = start_read_files.elapsed();
 
-    println!("got file paths: {elapsed_read_files:.2?}");
+    eprintln!("got file paths: {elapsed_read_files:.2?}");
 
-    println!("Parsing files...");
-
-    let read_files_total = Instant::now();
-
-    /*
-    
-      {
-    "endTime" : "2023-08-27 22:44",
-    "artistName" : "Vincent Neil Emerson",
-    "trackName" : "Manhattan Island Serenade",
-    "msPlayed" : 5150
-  },
-    
-    */
+    let cache = if use_cache { Cache::locate(data_path, &paths, lenient) } else { None };
+    if let Some(cache) = &cache {
+        let start = Instant::now();
+        if let Some(tbl) = cache.read() {
+            eprintln!("Loaded {} plays from {}: {:.2?}", tbl.len(), cache.path().display(), start.elapsed());
+            return Ok(tbl);
+        }
+    }
 
-    let mut tbl = Table::new(BIG_HISTORY_TABLE);
+    eprintln!("Parsing files...");
 
-    let mut handles = vec![];
+    let read_files_total = Instant::now();
 
+    let mut exports = Vec::new();
     for path in paths {
-
-        let handle = thread::spawn(|| {
-            parse_file(path)
-        });
-
-        handles.push(handle);
+        // a json file that isn't an export needn't be valid for the rest to load
+        match path.detect() {
+            Ok(Some(format)) => exports.push((path, format)),
+            Ok(None) => eprintln!("skipping {}: not a streaming history export", path.name()),
+            Err(e) => eprintln!("skipping {}: not a streaming history export, it doesn't parse: {e}", path.name()),
+        }
     }
 
+    let mut tbl = Table::new(BIG_HISTORY_TABLE);
+    let mut rejected: Vec<Error> = Vec::new();
+
+    for parsed in parse_parallel(exports, threads, lenient)? {
+        let name = parsed.source.name();
+        for (key, count) in &parsed.report.unknown_keys {
+            eprintln!("{name}: ignored unknown key '{key}' ({count} times)");
+        }
+
+        match parsed.chunks {
+            1 => eprintln!("[{name}] parsing took {:.2?}", parsed.took),
+            chunks => eprintln!("[{name}] parsing took {:.2?} in {chunks} chunks", parsed.took),
+        }
+        tbl.append(parsed.table)?;
+        rejected.extend(parsed.report.rejected);
+    }
 
-    let mut res: Vec<(u8, Table, Duration)> = handles.into_iter().map(|t| t.join().expect("thread failed")).collect();
-    res.sort_by(|a, b| a.0.cmp(&b.0));
-
-    for table in res {
-        println!("[THREAD {}] parsing took {:.2?}", table.0, table.2);
-        tbl.rows.extend(table.1.rows);
+    // exports overlap and their names say little about what they cover, so
+    // plays go in the order they happened, ties in the order they were read
+    tbl = tbl.sort_by(&[SortKey::asc("time")])?;
+
+    if !rejected.is_empty() {
+        eprintln!("rejected {} records:", rejected.len());
+        for e in rejected.iter().take(MAX_REJECTS_SHOWN) {
+            eprintln!("    - {e}");
+        }
+        if rejected.len() > MAX_REJECTS_SHOWN {
+            eprintln!("    ... and {} more", rejected.len() - MAX_REJECTS_SHOWN);
+        }
     }
 
     let elapsed_files_total = read_files_total.elapsed();
-    println!("Parsed files: {elapsed_files_total:.2?}");
-
-
-    let before_query = Instant::now();
-
-    //tbl = tbl.field_in_range("time", &quick_date(2019, 12, 31).into(), &quick_date(2020, 01, 30).into()).unwrap();
-    //tbl = tbl.sort_by("msplayed").unwrap();
+    eprintln!("Parsed {} plays: {elapsed_files_total:.2?}", tbl.len());
+
+    // loads that rejected records aren't kept, so the rejects are reported
+    // every run until they're fixed
+    if let Some(cache) = cache.filter(|_| rejected.is_empty()) {
+        if let Err(e) = cache.write(&tbl) {
+            eprintln!("couldn't write the cache to {}: {e}", cache.path().display());
+        }
+    }
 
-    tbl = tbl.field_is_greater_than("msplayed", &3000.into()).unwrap();
-    tbl = tbl.field_is("artist", &"lost dog street band".into()).unwrap();
+    Ok(tbl)
+}
 
-    println!("QUERY TOOK: {:.2?}", before_query.elapsed());
+fn main() -> Result<()> {
+    let args = Args::from_env()?;
 
-    tbl = tbl.select([ "time", "song", "artist", "msplayed" ]);
+    if args.command == Command::Help {
+        print_usage();
+        return Ok(());
+    }
 
-    //println!("{}", tbl);
-    println!("first row:\n{}", tbl.take_first().unwrap());
+    let mut tbl = load(&args.data, args.lenient, args.cache, args.threads)?;
+    if let Some(tz) = &args.tz {
+        tbl = tbl.to_zone(tz);
+    }
 
-    //grouped = grouped.sort_by("COUNT").unwrap();
+    let before_query = Instant::now();
+    run(&args, tbl)?;
+    eprintln!("QUERY TOOK: {:.2?}", before_query.elapsed());
 
-    
-    //println!("unique tracks: {}", grouped);
+    Ok(())
 }
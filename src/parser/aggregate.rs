@@ -0,0 +1,217 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+};
+
+use super::table::{DataErrors, Field};
+
+/// What `Table::group_by` computes per group besides the key columns.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Aggregate {
+    /// rows in the group
+    Count,
+    Sum(String),
+    /// rounded to the nearest whole number
+    Avg(String),
+    Min(String),
+    Max(String),
+    CountDistinct(String),
+}
+
+impl Aggregate {
+    /// Column the aggregate reads, `None` for `Count`.
+    pub fn column(&self) -> Option<&str> {
+        match self {
+            Aggregate::Count => None,
+            Aggregate::Sum(c) | Aggregate::Avg(c) | Aggregate::Min(c) | Aggregate::Max(c) | Aggregate::CountDistinct(c) => Some(c),
+        }
+    }
+
+    pub fn from_name(func: &str, column: Option<&str>) -> Option<Self> {
+        let column = column.map(str::to_owned);
+
+        Some(match (func.to_lowercase().as_str(), column) {
+            ("count", None) => Aggregate::Count,
+            ("sum", Some(c)) => Aggregate::Sum(c),
+            ("avg", Some(c)) => Aggregate::Avg(c),
+            ("min", Some(c)) => Aggregate::Min(c),
+            ("max", Some(c)) => Aggregate::Max(c),
+            ("count_distinct", Some(c)) => Aggregate::CountDistinct(c),
+            _ => return None,
+        })
+    }
+}
+
+/// The header the aggregate gets, e.g. `sum(msplayed)`.
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregate::Count => f.write_str("count"),
+            Aggregate::Sum(c) => write!(f, "sum({c})"),
+            Aggregate::Avg(c) => write!(f, "avg({c})"),
+            Aggregate::Min(c) => write!(f, "min({c})"),
+            Aggregate::Max(c) => write!(f, "max({c})"),
+            Aggregate::CountDistinct(c) => write!(f, "count_distinct({c})"),
+        }
+    }
+}
+
+/// Running state of one aggregate for one group. Nulls are skipped by
+/// everything but `Count`.
+pub(crate) enum Accumulator {
+    Count(u64),
+    Sum(u64),
+    Avg { sum: u64, count: u64 },
+    Min(Field),
+    Max(Field),
+    Distinct(HashSet<Field>),
+}
+
+fn number(field: &Field, agg: &str) -> Result<u64, DataErrors> {
+    match field {
+        Field::Number(n) => Ok(*n),
+        other => Err(DataErrors::WrongType(format!("{agg} expects numbers, got '{other}'"))),
+    }
+}
+
+/// `sum + field`, an error rather than wrapping when it doesn't fit.
+fn add_number(sum: u64, field: &Field, agg: &str) -> Result<u64, DataErrors> {
+    let n = number(field, agg)?;
+    sum.checked_add(n).ok_or_else(|| DataErrors::Overflow(format!("{agg} is too large, adding {n} to {sum}")))
+}
+
+impl Accumulator {
+    pub(crate) fn new(agg: &Aggregate) -> Self {
+        match agg {
+            Aggregate::Count => Accumulator::Count(0),
+            Aggregate::Sum(_) => Accumulator::Sum(0),
+            Aggregate::Avg(_) => Accumulator::Avg { sum: 0, count: 0 },
+            Aggregate::Min(_) => Accumulator::Min(Field::Null),
+            Aggregate::Max(_) => Accumulator::Max(Field::Null),
+            Aggregate::CountDistinct(_) => Accumulator::Distinct(HashSet::new()),
+        }
+    }
+
+    /// `field` is the value of the aggregated column, `Field::Null` for `Count`.
+    pub(crate) fn add(&mut self, field: &Field) -> Result<(), DataErrors> {
+        if let Accumulator::Count(n) = self {
+            *n += 1;
+            return Ok(());
+        }
+
+        if field.is_null() {
+            return Ok(());
+        }
+
+        match self {
+            Accumulator::Count(_) => {}
+            Accumulator::Sum(sum) => *sum = add_number(*sum, field, "sum")?,
+            Accumulator::Avg { sum, count } => {
+                *sum = add_number(*sum, field, "avg")?;
+                *count += 1;
+            }
+            Accumulator::Min(min) => {
+                if min.is_null() || field < min {
+                    *min = field.clone();
+                }
+            }
+            Accumulator::Max(max) => {
+                if max.is_null() || field > max {
+                    *max = field.clone();
+                }
+            }
+            Accumulator::Distinct(seen) => {
+                if !seen.contains(field) {
+                    seen.insert(field.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Field {
+        match self {
+            Accumulator::Count(n) | Accumulator::Sum(n) => Field::Number(n),
+            Accumulator::Avg { count: 0, .. } => Field::Null,
+            // halves round up, without adding to a sum that may be near the limit
+            Accumulator::Avg { sum, count } => Field::Number(sum / count + u64::from(sum % count >= count - sum % count)),
+            Accumulator::Min(f) | Accumulator::Max(f) => f,
+            Accumulator::Distinct(seen) => Field::Number(seen.len() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(agg: &Aggregate, fields: &[Field]) -> Result<Field, DataErrors> {
+        let mut acc = Accumulator::new(agg);
+        for field in fields {
+            acc.add(field)?;
+        }
+        Ok(acc.finish())
+    }
+
+    fn numbers(ns: &[u64]) -> Vec<Field> {
+        ns.iter().map(|n| Field::Number(*n)).collect()
+    }
+
+    #[test]
+    fn sums() {
+        let sum = Aggregate::Sum("msplayed".into());
+        assert_eq!(run(&sum, &numbers(&[1, 2, 3])).unwrap(), Field::Number(6));
+        assert_eq!(run(&sum, &[Field::Number(4), Field::Null, Field::Number(5)]).unwrap(), Field::Number(9));
+        assert_eq!(run(&sum, &[]).unwrap(), Field::Number(0));
+        assert_eq!(run(&sum, &numbers(&[u64::MAX - 1, 1])).unwrap(), Field::Number(u64::MAX));
+
+        let e = run(&sum, &numbers(&[u64::MAX, 1])).unwrap_err();
+        assert!(matches!(e, DataErrors::Overflow(_)), "{e:?}");
+        let e = run(&sum, &[Field::String("Tyler Childers".into())]).unwrap_err();
+        assert_eq!(e.to_string(), "sum expects numbers, got 'Tyler Childers'");
+    }
+
+    #[test]
+    fn averages_round_to_the_nearest() {
+        let avg = Aggregate::Avg("msplayed".into());
+        assert_eq!(run(&avg, &numbers(&[1, 2])).unwrap(), Field::Number(2));
+        assert_eq!(run(&avg, &numbers(&[1, 2, 2])).unwrap(), Field::Number(2));
+        assert_eq!(run(&avg, &numbers(&[1, 1, 2])).unwrap(), Field::Number(1));
+        assert_eq!(run(&avg, &[Field::Null, Field::Number(7), Field::Null]).unwrap(), Field::Number(7));
+        assert_eq!(run(&avg, &[Field::Null]).unwrap(), Field::Null);
+        assert_eq!(run(&avg, &numbers(&[u64::MAX, 0])).unwrap(), Field::Number(u64::MAX / 2 + 1));
+        assert_eq!(run(&avg, &numbers(&[u64::MAX])).unwrap(), Field::Number(u64::MAX));
+        assert!(matches!(run(&avg, &numbers(&[u64::MAX, u64::MAX])), Err(DataErrors::Overflow(_))));
+    }
+
+    #[test]
+    fn min_and_max() {
+        let fields = [Field::Null, Field::Number(3), Field::Number(1), Field::Null, Field::Number(2)];
+        assert_eq!(run(&Aggregate::Min("msplayed".into()), &fields).unwrap(), Field::Number(1));
+        assert_eq!(run(&Aggregate::Max("msplayed".into()), &fields).unwrap(), Field::Number(3));
+        assert_eq!(run(&Aggregate::Min("msplayed".into()), &[Field::Null]).unwrap(), Field::Null);
+
+        let names = [Field::String("b".into()), Field::String("a".into()), Field::String("c".into())];
+        assert_eq!(run(&Aggregate::Min("artist".into()), &names).unwrap(), Field::String("a".into()));
+        assert_eq!(run(&Aggregate::Max("artist".into()), &names).unwrap(), Field::String("c".into()));
+    }
+
+    #[test]
+    fn counts() {
+        let fields = [Field::String("a".into()), Field::Null, Field::String("b".into()), Field::String("a".into()), Field::Null];
+        assert_eq!(run(&Aggregate::Count, &fields).unwrap(), Field::Number(5));
+        assert_eq!(run(&Aggregate::CountDistinct("artist".into()), &fields).unwrap(), Field::Number(2));
+        assert_eq!(run(&Aggregate::Count, &[]).unwrap(), Field::Number(0));
+        assert_eq!(run(&Aggregate::CountDistinct("artist".into()), &[Field::Null]).unwrap(), Field::Number(0));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Aggregate::from_name("SUM", Some("msplayed")), Some(Aggregate::Sum("msplayed".into())));
+        assert_eq!(Aggregate::from_name("count", None), Some(Aggregate::Count));
+        assert_eq!(Aggregate::from_name("count", Some("msplayed")), None);
+        assert_eq!(Aggregate::from_name("sum", None), None);
+        assert_eq!(Aggregate::CountDistinct("artist".into()).to_string(), "count_distinct(artist)");
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
+
+use super::{fuzzy, intern::Symbol, parse::DateTime, table::Field};
+
+/// What the slots of null rows hold in a date column, never looked at.
+const NO_DATE: DateTime = DateTime { day: 1, month: 1, year: 1970, hour: 0, minute: 0, second: 0 };
+
+/// One bit per row, set where the row has a value.
+#[derive(Clone, Debug, Default)]
+pub struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// The bits 64 at a time, row 0 in the lowest bit of the first word.
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// The bitmap `words` gives for `len` rows, `None` when there are too
+    /// few or too many of them. Bits past `len` are dropped.
+    pub fn from_words(mut words: Vec<u64>, len: usize) -> Option<Bitmap> {
+        if words.len() != len.div_ceil(64) {
+            return None;
+        }
+        if let Some(last) = words.last_mut().filter(|_| !len.is_multiple_of(64)) {
+            *last &= (1 << (len % 64)) - 1;
+        }
+        Some(Bitmap { words, len })
+    }
+}
+
+impl FromIterator<bool> for Bitmap {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut bitmap = Bitmap::default();
+        for bit in iter {
+            bitmap.push(bit);
+        }
+        bitmap
+    }
+}
+
+/// The values of a column, one slot per row. Slots of null rows hold a
+/// placeholder, the validity bitmap of the `Column` says which those are.
+#[derive(Clone, Debug)]
+pub enum Values {
+    /// no row has had a value yet
+    Empty,
+    Number(Vec<u64>),
+    Bool(Vec<bool>),
+    Date(Vec<DateTime>),
+    String(Vec<String>),
+    /// strings that repeat a lot, see `Symbol`
+    Symbols(Vec<Symbol>),
+    /// values of more than one kind, as a computed column can have
+    Mixed(Vec<Field>),
+}
+
+impl Values {
+    /// Slots held, `None` for `Empty` which has as many as there are rows.
+    fn len(&self) -> Option<usize> {
+        match self {
+            Values::Empty => None,
+            Values::Number(v) => Some(v.len()),
+            Values::Bool(v) => Some(v.len()),
+            Values::Date(v) => Some(v.len()),
+            Values::String(v) => Some(v.len()),
+            Values::Symbols(v) => Some(v.len()),
+            Values::Mixed(v) => Some(v.len()),
+        }
+    }
+}
+
+/// A typed vector of one column with a validity bitmap for its nulls. The type
+/// is set by the first value that isn't null.
+#[derive(Clone, Debug)]
+pub struct Column {
+    values: Values,
+    valid: Bitmap,
+    /// strings are interned
+    interned: bool,
+}
+
+impl Column {
+    pub fn new(interned: bool) -> Self {
+        Column { values: Values::Empty, valid: Bitmap::default(), interned }
+    }
+
+    /// A column of `values` with `valid` marking the rows that aren't null,
+    /// `None` when they don't agree on the number of rows.
+    pub fn from_parts(values: Values, valid: Bitmap, interned: bool) -> Option<Self> {
+        let agrees = match values.len() {
+            Some(len) => len == valid.len(),
+            None => valid.count_ones() == 0,
+        };
+        agrees.then_some(Column { values, valid, interned })
+    }
+
+    pub fn is_interned(&self) -> bool {
+        self.interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.valid.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.valid.is_empty()
+    }
+
+    pub fn values(&self) -> &Values {
+        &self.values
+    }
+
+    pub fn validity(&self) -> &Bitmap {
+        &self.valid
+    }
+
+    pub fn is_null(&self, row: usize) -> bool {
+        !self.valid.get(row)
+    }
+
+    pub fn get(&self, row: usize) -> Field {
+        if !self.valid.get(row) {
+            return Field::Null;
+        }
+
+        match &self.values {
+            Values::Empty => Field::Null,
+            Values::Number(v) => Field::Number(v[row]),
+            Values::Bool(v) => Field::Bool(v[row]),
+            Values::Date(v) => Field::Date(v[row]),
+            Values::String(v) => Field::String(v[row].clone()),
+            Values::Symbols(v) => Field::String(v[row].as_str().to_owned()),
+            Values::Mixed(v) => v[row].clone(),
+        }
+    }
+
+    /// The text of `row` without copying it, `None` for nulls and other values.
+    pub fn get_str(&self, row: usize) -> Option<&str> {
+        if !self.valid.get(row) {
+            return None;
+        }
+
+        match &self.values {
+            Values::String(v) => Some(&v[row]),
+            Values::Symbols(v) => Some(v[row].as_str()),
+            Values::Mixed(v) => match &v[row] {
+                Field::String(s) => Some(s),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// A number for the value of every row that orders like the values, so
+    /// sorting compares integers rather than fields. What null rows get
+    /// means nothing. Symbols are ranked by a table of each distinct one.
+    pub fn ranks(&self) -> Vec<u64> {
+        /// Where the value of each row comes once they're sorted, equal values equal.
+        fn rank_by<T: Ord>(len: usize, value: impl Fn(usize) -> T) -> Vec<u64> {
+            let mut order: Vec<usize> = (0..len).collect();
+            order.sort_by_key(|i| value(*i));
+
+            let mut ranks = vec![0; len];
+            for pair in order.windows(2) {
+                ranks[pair[1]] = ranks[pair[0]] + (value(pair[0]) != value(pair[1])) as u64;
+            }
+            ranks
+        }
+
+        let len = self.len();
+        match &self.values {
+            Values::Empty => vec![0; len],
+            Values::Number(v) => v.clone(),
+            Values::Bool(v) => v.iter().map(|b| *b as u64).collect(),
+            // flipping the sign bit keeps the order of negative timestamps
+            Values::Date(v) => v.iter().map(|d| d.timestamp() as u64 ^ (1 << 63)).collect(),
+            Values::String(v) => rank_by(len, |i| &v[i]),
+            Values::Symbols(v) => {
+                let mut distinct: Vec<Symbol> = v.iter().copied().collect::<HashSet<_>>().into_iter().collect();
+                distinct.sort_by_key(|s| s.as_str());
+                let rank: HashMap<Symbol, u64> = distinct.into_iter().enumerate().map(|(n, s)| (s, n as u64)).collect();
+                v.iter().map(|s| rank[s]).collect()
+            }
+            Values::Mixed(v) => rank_by(len, |i| &v[i]),
+        }
+    }
+
+    /// Whether `field` can go in the typed vector as it is.
+    fn fits(&self, field: &Field) -> bool {
+        matches!(
+            (&self.values, field),
+            (_, Field::Null)
+                | (Values::Empty | Values::Mixed(_), _)
+                | (Values::Number(_), Field::Number(_))
+                | (Values::Bool(_), Field::Bool(_))
+                | (Values::Date(_), Field::Date(_))
+                | (Values::String(_) | Values::Symbols(_), Field::String(_))
+        )
+    }
+
+    pub fn push(&mut self, field: Field) {
+        let len = self.len();
+
+        if !self.fits(&field) {
+            self.values = Values::Mixed((0..len).map(|i| self.get(i)).collect());
+        }
+
+        if let Values::Empty = self.values {
+            self.values = match &field {
+                Field::Null => Values::Empty,
+                Field::Number(_) => Values::Number(vec![0; len]),
+                Field::Bool(_) => Values::Bool(vec![false; len]),
+                Field::Date(_) => Values::Date(vec![NO_DATE; len]),
+                Field::String(_) if self.interned => Values::Symbols(vec![Symbol::intern(""); len]),
+                Field::String(_) => Values::String(vec![String::new(); len]),
+            };
+        }
+
+        self.valid.push(!field.is_null());
+        match (&mut self.values, field) {
+            (Values::Empty, _) => {}
+            (Values::Number(v), Field::Number(n)) => v.push(n),
+            (Values::Number(v), _) => v.push(0),
+            (Values::Bool(v), Field::Bool(b)) => v.push(b),
+            (Values::Bool(v), _) => v.push(false),
+            (Values::Date(v), Field::Date(d)) => v.push(d),
+            (Values::Date(v), _) => v.push(NO_DATE),
+            (Values::String(v), Field::String(s)) => v.push(s),
+            (Values::String(v), _) => v.push(String::new()),
+            (Values::Symbols(v), Field::String(s)) => v.push(Symbol::intern(&s)),
+            (Values::Symbols(v), _) => v.push(Symbol::intern("")),
+            (Values::Mixed(v), field) => v.push(field),
+        }
+    }
+
+    /// Adds the rows of `other` after the ones already here.
+    pub fn append(&mut self, other: Column) {
+        if self.is_empty() {
+            *self = Column { interned: self.interned, ..other };
+            return;
+        }
+
+        let other_len = other.len();
+        match (&mut self.values, other.values) {
+            (Values::Number(a), Values::Number(b)) => a.extend(b),
+            (Values::Bool(a), Values::Bool(b)) => a.extend(b),
+            (Values::Date(a), Values::Date(b)) => a.extend(b),
+            (Values::String(a), Values::String(b)) => a.extend(b),
+            (Values::Symbols(a), Values::Symbols(b)) => a.extend(b),
+            (_, values) => {
+                let other = Column { values, valid: other.valid, interned: other.interned };
+                for i in 0..other_len {
+                    self.push(other.get(i));
+                }
+                return;
+            }
+        }
+
+        for i in 0..other_len {
+            self.valid.push(other.valid.get(i));
+        }
+    }
+
+    /// Keeps the rows where `keep` is set.
+    pub fn retain(&mut self, keep: &[bool]) {
+        fn retain<T>(v: &mut Vec<T>, keep: &[bool]) {
+            let mut i = 0;
+            v.retain(|_| {
+                i += 1;
+                keep[i - 1]
+            });
+        }
+
+        match &mut self.values {
+            Values::Empty => {}
+            Values::Number(v) => retain(v, keep),
+            Values::Bool(v) => retain(v, keep),
+            Values::Date(v) => retain(v, keep),
+            Values::String(v) => retain(v, keep),
+            Values::Symbols(v) => retain(v, keep),
+            Values::Mixed(v) => retain(v, keep),
+        }
+
+        self.valid = keep.iter().enumerate().filter(|(_, k)| **k).map(|(i, _)| self.valid.get(i)).collect();
+    }
+
+    /// Puts the rows in the order of `order`, which holds every row once.
+    pub fn permute(&mut self, order: &[usize]) {
+        fn permute<T: Default>(v: &mut Vec<T>, order: &[usize]) {
+            let mut old = mem::take(v);
+            *v = order.iter().map(|i| mem::take(&mut old[*i])).collect();
+        }
+
+        match &mut self.values {
+            Values::Empty => {}
+            Values::Number(v) => permute(v, order),
+            Values::Bool(v) => permute(v, order),
+            Values::Date(v) => *v = order.iter().map(|i| v[*i]).collect(),
+            Values::String(v) => permute(v, order),
+            Values::Symbols(v) => *v = order.iter().map(|i| v[*i]).collect(),
+            Values::Mixed(v) => *v = order.iter().map(|i| mem::replace(&mut v[*i], Field::Null)).collect(),
+        }
+
+        self.valid = order.iter().map(|i| self.valid.get(*i)).collect();
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len() {
+            return;
+        }
+
+        match &mut self.values {
+            Values::Empty => {}
+            Values::Number(v) => v.truncate(len),
+            Values::Bool(v) => v.truncate(len),
+            Values::Date(v) => v.truncate(len),
+            Values::String(v) => v.truncate(len),
+            Values::Symbols(v) => v.truncate(len),
+            Values::Mixed(v) => v.truncate(len),
+        }
+
+        self.valid = (0..len).map(|i| self.valid.get(i)).collect();
+    }
+
+    /// Replaces every date with `f` of it.
+    pub fn map_dates<F: Fn(&DateTime) -> DateTime>(&mut self, f: F) {
+        match &mut self.values {
+            Values::Date(v) => {
+                for (i, date) in v.iter_mut().enumerate() {
+                    if self.valid.get(i) {
+                        *date = f(date);
+                    }
+                }
+            }
+            Values::Mixed(v) => {
+                for field in v {
+                    if let Field::Date(date) = field {
+                        *date = f(date);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A code for the value of every row, equal where the values are. Nulls
+    /// get a code too.
+    pub fn codes(&self) -> Vec<u32> {
+        let len = self.len();
+
+        match &self.values {
+            // symbols already are, shifted by one so 0 is null
+            Values::Symbols(v) => (0..len).map(|i| if self.valid.get(i) { v[i].id() + 1 } else { 0 }).collect(),
+            Values::Number(v) => {
+                let mut index: HashMap<Option<u64>, u32> = HashMap::new();
+                (0..len).map(|i| {
+                    let next = index.len() as u32;
+                    *index.entry(self.valid.get(i).then_some(v[i])).or_insert(next)
+                }).collect()
+            }
+            _ => {
+                let mut index: HashMap<Field, u32> = HashMap::new();
+                (0..len).map(|i| {
+                    let next = index.len() as u32;
+                    *index.entry(self.get(i)).or_insert(next)
+                }).collect()
+            }
+        }
+    }
+
+    /// Whether a row holds exactly the text `s`. Symbols are compared as
+    /// symbols, a string nothing interned can't be in the column.
+    pub fn has_text(&self, s: &str) -> bool {
+        match &self.values {
+            Values::Symbols(v) => Symbol::lookup(s).is_some_and(|symbol| v.iter().enumerate().any(|(i, x)| *x == symbol && self.valid.get(i))),
+            Values::String(v) => v.iter().enumerate().any(|(i, x)| x == s && self.valid.get(i)),
+            Values::Mixed(v) => v.iter().any(|f| matches!(f, Field::String(x) if x == s)),
+            _ => false,
+        }
+    }
+
+    /// The distinct texts most like `query`, see `fuzzy::rank`.
+    pub fn fuzzy_find(&self, query: &str, limit: usize) -> Vec<(String, f64)> {
+        let distinct = self.first_of_each().into_iter().filter_map(|row| self.get_str(row));
+        fuzzy::rank(query, distinct, limit).into_iter().map(|(s, score)| (s.to_owned(), score)).collect()
+    }
+
+    /// Rows holding the first of each distinct value, in the order they appear.
+    pub fn first_of_each(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        self.codes().into_iter().enumerate().filter(|(_, code)| seen.insert(*code)).map(|(row, _)| row).collect()
+    }
+}
@@ -0,0 +1,504 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use crate::error::{self, Error};
+
+use super::{
+    format::format_date,
+    parse::{to_timestamp_big_history, DateTime, DebugInfo, FieldKind, BIG_HISTORY_SCHEMA},
+    column::Column,
+    parse_arguments::Position,
+    table::{DataErrors, Field, Table, INTERNED_COLUMNS},
+    tz::{format_offset, parse_offset, TimeZone},
+};
+
+/// How dates are written, read back by `CsvBuilder` and understood by
+/// spreadsheets. Times in a zone other than UTC get its offset after them.
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug)]
+pub enum CsvError {
+    /// input ended inside a quoted field
+    UnterminatedQuote,
+    /// something other than a separator right after a closing quote
+    UnexpectedChar(char),
+    /// a record with another number of fields than the header
+    FieldCount { expected: usize, found: usize },
+    /// not even a header
+    Empty,
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::UnterminatedQuote => f.write_str("quoted field never ends"),
+            CsvError::UnexpectedChar(c) => write!(f, "expected ',' or a line break after a quoted field, found '{c}'"),
+            CsvError::FieldCount { expected, found } => write!(f, "expected {expected} fields, found {found}"),
+            CsvError::Empty => f.write_str("no header"),
+        }
+    }
+}
+
+/// Whether an unquoted `field` would be read back as something else than text.
+fn looks_typed(field: &str) -> bool {
+    field.is_empty()
+        || field.bytes().all(|b| b.is_ascii_digit())
+        || matches!(field, "true" | "false")
+        || parse_date(field).is_some()
+}
+
+/// `field` quoted when it has to be. Strings that look like nulls, numbers,
+/// bools or dates are quoted too, so they read back as strings.
+fn quote(field: &str) -> String {
+    let needs_quotes = looks_typed(field)
+        || field.contains([',', '"', '\r', '\n'])
+        || field.starts_with(char::is_whitespace)
+        || field.ends_with(char::is_whitespace);
+
+    match needs_quotes {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+impl Table {
+    /// Writes the header and rows as RFC 4180 CSV. Nulls are empty fields and
+    /// dates are `yyyy-mm-dd hh:mm:ss`, which `CsvBuilder` reads back. When the
+    /// table's times are in `zone` they end with its offset, e.g. `+02:00`, so
+    /// they load back as the same instants.
+    pub fn write_csv<W: Write>(&self, out: &mut W, zone: Option<&TimeZone>) -> io::Result<()> {
+        let header: Vec<String> = self.header.iter().map(|(name, _)| quote(name)).collect();
+        write!(out, "{}\r\n", header.join(","))?;
+
+        for row in 0..self.len() {
+            let fields: Vec<String> = self
+                .header
+                .iter()
+                .map(|(_, col)| match self.get(row, *col) {
+                    Field::Null => String::new(),
+                    Field::String(s) => quote(&s),
+                    Field::Date(d) => match zone {
+                        Some(tz) => format_date(&d, DATE_FORMAT) + &format_offset(tz.offset_at_local(d.timestamp())),
+                        None => format_date(&d, DATE_FORMAT),
+                    },
+                    other => other.to_string(),
+                })
+                .collect();
+            write!(out, "{}\r\n", fields.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A field as written, before it's given a type. An empty unquoted field is a null.
+struct RawField {
+    text: String,
+    quoted: bool,
+}
+
+impl RawField {
+    fn is_null(&self) -> bool {
+        !self.quoted && self.text.is_empty()
+    }
+}
+
+/// Reads records a line at a time, so the input is never all in memory.
+/// Reading fails like the input ended, with the error kept in `failed`.
+struct Reader<R> {
+    input: R,
+    /// the line being read, empty once the input is used up
+    line: String,
+    /// where in `line` reading is
+    at: usize,
+    /// bytes before `line`
+    offset: usize,
+    line_number: usize,
+    failed: Option<io::Error>,
+}
+
+impl<R: BufRead> Reader<R> {
+    fn new(input: R) -> Self {
+        let mut reader = Reader { input, line: String::new(), at: 0, offset: 0, line_number: 1, failed: None };
+        reader.next_line();
+        // spreadsheets like to start with a byte order mark
+        if reader.line.starts_with('\u{feff}') {
+            reader.line.drain(..'\u{feff}'.len_utf8());
+        }
+        reader
+    }
+
+    fn position(&self) -> Position {
+        Position { offset: self.offset + self.at, line: self.line_number, column: self.at + 1 }
+    }
+
+    fn next_line(&mut self) {
+        self.offset += self.line.len();
+        if self.line.ends_with('\n') {
+            self.line_number += 1;
+        }
+        self.line.clear();
+        self.at = 0;
+
+        if let Err(e) = self.input.read_line(&mut self.line) {
+            self.line.clear();
+            self.failed = Some(e);
+        }
+    }
+
+    /// Moves `len` bytes on, to the next line when that's the end of this one.
+    fn skip(&mut self, len: usize) {
+        self.at += len;
+        if self.at == self.line.len() {
+            self.next_line();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.line[self.at..].chars().next()
+    }
+
+    fn pop(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.skip(c.len_utf8());
+        Some(c)
+    }
+
+    fn field(&mut self) -> Result<RawField, CsvError> {
+        let rest = &self.line[self.at..];
+        if !rest.starts_with('"') {
+            let len = rest.find([',', '\r', '\n']).unwrap_or(rest.len());
+            let text = rest[..len].to_owned();
+            self.skip(len);
+            return Ok(RawField { text, quoted: false });
+        }
+
+        self.pop();
+        let mut text = String::new();
+        loop {
+            // up to the next quote, which may be lines away
+            let rest = &self.line[self.at..];
+            let Some(len) = rest.find('"') else {
+                if rest.is_empty() {
+                    return Err(CsvError::UnterminatedQuote);
+                }
+                text += rest;
+                self.skip(rest.len());
+                continue;
+            };
+
+            text += &rest[..len];
+            self.skip(len + 1);
+            match self.peek() {
+                Some('"') => {
+                    self.pop();
+                    text.push('"');
+                }
+                _ => return Ok(RawField { text, quoted: true }),
+            }
+        }
+    }
+
+    /// The next record, `None` once the input is used up. Blank lines are skipped.
+    fn record(&mut self) -> Result<Option<Vec<RawField>>, CsvError> {
+        loop {
+            if self.peek().is_none() {
+                return Ok(None);
+            }
+
+            let mut fields = vec![self.field()?];
+            loop {
+                match self.pop() {
+                    Some(',') => fields.push(self.field()?),
+                    Some('\r') if self.peek() == Some('\n') => {
+                        self.pop();
+                        break;
+                    }
+                    Some('\r' | '\n') | None => break,
+                    Some(c) => return Err(CsvError::UnexpectedChar(c)),
+                }
+            }
+
+            if fields.len() > 1 || !fields[0].is_null() {
+                return Ok(Some(fields));
+            }
+        }
+    }
+}
+
+/// A date as `Table::write_csv` writes them or as in the exports, in UTC. One
+/// ending with an offset like `+02:00` is moved back from it.
+fn parse_date(value: &str) -> Option<DateTime> {
+    let (value, offset) = match value.strip_suffix('Z') {
+        Some(value) => (value, 0),
+        None => value
+            .len()
+            .checked_sub("+00:00".len())
+            .filter(|at| value.is_char_boundary(*at))
+            .and_then(|at| Some((&value[..at], parse_offset(&value[at..])?)))
+            .unwrap_or((value, 0)),
+    };
+
+    let date = DateTime::from_str(value).or_else(|_| to_timestamp_big_history(value)).ok()?;
+    match offset {
+        0 => Some(date),
+        offset => DateTime::from_timestamp(date.timestamp() - offset as i64),
+    }
+}
+
+/// The narrowest kind every non-null value of a column fits. Anything quoted
+/// makes it text.
+fn guess_kind<'a, I: Iterator<Item = &'a RawField>>(values: I) -> FieldKind {
+    let values: Vec<&RawField> = values.filter(|v| !v.is_null()).collect();
+    if values.iter().any(|v| v.quoted) {
+        return FieldKind::String;
+    }
+    let values: Vec<&str> = values.iter().map(|v| v.text.as_str()).collect();
+
+    if values.is_empty() {
+        FieldKind::String
+    } else if values.iter().all(|v| v.bytes().all(|b| b.is_ascii_digit()) && v.parse::<u64>().is_ok()) {
+        FieldKind::Number
+    } else if values.iter().all(|v| matches!(*v, "true" | "false")) {
+        FieldKind::Bool
+    } else if values.iter().all(|v| parse_date(v).is_some()) {
+        FieldKind::Date
+    } else {
+        FieldKind::String
+    }
+}
+
+fn to_field(kind: FieldKind, raw: &RawField) -> Option<Field> {
+    if raw.is_null() {
+        return Some(Field::Null);
+    }
+
+    Some(match kind {
+        FieldKind::String => Field::String(raw.text.clone()),
+        FieldKind::Number => Field::Number(raw.text.parse().ok()?),
+        FieldKind::Bool => Field::Bool(raw.text.parse().ok()?),
+        FieldKind::Date => Field::Date(parse_date(&raw.text)?),
+    })
+}
+
+/// Loads CSV, such as `Table::write_csv` gives, back into a `Table`. Columns
+/// named like those of the exports get their kind, the rest get whatever kind
+/// all of their values fit, falling back to strings.
+pub struct CsvBuilder {
+    kinds: BTreeMap<String, FieldKind>,
+}
+
+impl Default for CsvBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvBuilder {
+    pub fn new() -> Self {
+        let kinds = BIG_HISTORY_SCHEMA.keys.iter().map(|(_, column, kind)| (column.to_string(), *kind)).collect();
+        CsvBuilder { kinds }
+    }
+
+    /// Reads `column` as `kind` instead of guessing.
+    pub fn kind(mut self, column: &str, kind: FieldKind) -> Self {
+        self.kinds.insert(column.to_owned(), kind);
+        self
+    }
+
+    /// `file_path` is only used to point at problems. Columns of a known kind
+    /// are typed as they're read, the others are kept as written until all of
+    /// their values have been seen.
+    pub fn read<R: BufRead>(&self, reader: R, file_path: OsString) -> error::Result<Table> {
+        let mut reader = Reader::new(reader);
+        let csv_error = |reader: &Reader<R>, e: CsvError| Error::new(e).with_debug(DebugInfo::at(&file_path, reader.position()));
+        let next = |reader: &mut Reader<R>| {
+            let record = reader.record();
+            match reader.failed.take() {
+                Some(e) => Err(Error::new(e).with_debug(DebugInfo::at(&file_path, reader.position()))),
+                None => record.map_err(|e| csv_error(reader, e)),
+            }
+        };
+
+        let header = match next(&mut reader)? {
+            Some(header) => header,
+            None => return Err(csv_error(&reader, CsvError::Empty)),
+        };
+
+        let kinds: Vec<Option<FieldKind>> = header.iter().map(|name| self.kinds.get(&name.text).copied()).collect();
+        let mut columns: Vec<Column> = header.iter().map(|name| Column::new(INTERNED_COLUMNS.contains(&name.text.as_str()))).collect();
+        let mut unknown: Vec<Vec<RawField>> = header.iter().map(|_| Vec::new()).collect();
+
+        loop {
+            let start = reader.position();
+            let Some(fields) = next(&mut reader)? else { break };
+            if fields.len() != header.len() {
+                let e = CsvError::FieldCount { expected: header.len(), found: fields.len() };
+                return Err(Error::new(e).with_debug(DebugInfo::at(&file_path, start)));
+            }
+
+            for (i, raw) in fields.into_iter().enumerate() {
+                let Some(kind) = kinds[i] else {
+                    unknown[i].push(raw);
+                    continue;
+                };
+
+                let field = to_field(kind, &raw).ok_or_else(|| {
+                    let kind = format!("{kind:?}").to_lowercase();
+                    let e = DataErrors::WrongType(format!("{} expects a {kind}, got '{}'", header[i].text, raw.text));
+                    Error::new(e).with_debug(DebugInfo::at(&file_path, start).with_key(&header[i].text))
+                })?;
+                columns[i].push(field);
+            }
+        }
+
+        // every value fits the kind guessed from them all
+        for (column, values) in columns.iter_mut().zip(unknown).filter(|(_, values)| !values.is_empty()) {
+            let kind = guess_kind(values.iter());
+            for raw in &values {
+                column.push(to_field(kind, raw).unwrap_or(Field::Null));
+            }
+        }
+
+        let header = header.into_iter().enumerate().map(|(i, name)| (name.text, i)).collect();
+        Ok(Table::from_columns(header, columns)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    fn load(text: &str) -> error::Result<Table> {
+        // a tiny buffer, so records and quoted fields span many reads
+        CsvBuilder::new().read(BufReader::with_capacity(3, text.as_bytes()), "test.csv".into())
+    }
+
+    fn error(text: &str) -> String {
+        load(text).err().unwrap().to_string()
+    }
+
+    fn write(tbl: &Table, zone: Option<&TimeZone>) -> String {
+        let mut out = Vec::new();
+        tbl.write_csv(&mut out, zone).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn rows(tbl: &Table) -> Vec<Vec<Field>> {
+        (0..tbl.len()).map(|row| tbl.header.iter().map(|(_, col)| tbl.get(row, *col)).collect()).collect()
+    }
+
+    fn date(s: &str) -> Field {
+        Field::Date(DateTime::from_str(s).unwrap())
+    }
+
+    fn strings(tbl: &Table) -> Vec<Vec<Field>> {
+        let mut out = vec![tbl.header.iter().map(|(name, _)| Field::String(name.clone())).collect()];
+        out.extend(rows(tbl));
+        out
+    }
+
+    #[test]
+    fn quotes_what_it_has_to() {
+        let mut tbl = Table::new(["note", "with, comma"]);
+        let notes = ["plain", "Hello, Goodbye", "say \"hi\"", " padded ", "Live: 1999", "1999", "true", "2023-08-27 22:44:00", ""];
+        for note in notes {
+            tbl.insert([Field::String(note.into()), Field::Null]).unwrap();
+        }
+
+        let text = write(&tbl, None);
+        assert_eq!(
+            text,
+            "note,\"with, comma\"\r\nplain,\r\n\"Hello, Goodbye\",\r\n\"say \"\"hi\"\"\",\r\n\" padded \",\r\nLive: 1999,\r\n\"1999\",\r\n\"true\",\r\n\"2023-08-27 22:44:00\",\r\n\"\",\r\n"
+        );
+        assert_eq!(strings(&load(&text).unwrap()), strings(&tbl));
+    }
+
+    #[test]
+    fn embedded_line_breaks() {
+        let mut tbl = Table::new(["note", "n"]);
+        tbl.insert([Field::String("two\nlines".into()), Field::Number(1)]).unwrap();
+        tbl.insert([Field::String("windows\r\nlines\r\n".into()), Field::Number(2)]).unwrap();
+        tbl.insert([Field::String("\n".into()), Field::Number(3)]).unwrap();
+
+        let text = write(&tbl, None);
+        assert_eq!(rows(&load(&text).unwrap()), rows(&tbl));
+        assert_eq!(rows(&load(&text.replace("\r\n", "\n")).unwrap())[2], vec![Field::String("\n".into()), Field::Number(3)]);
+    }
+
+    #[test]
+    fn null_is_not_the_empty_string() {
+        let mut tbl = Table::new(["song", "note"]);
+        tbl.insert([Field::String("".into()), Field::Null]).unwrap();
+        tbl.insert([Field::Null, Field::String("".into())]).unwrap();
+
+        let text = write(&tbl, None);
+        assert_eq!(text, "song,note\r\n\"\",\r\n,\"\"\r\n");
+        assert_eq!(rows(&load(&text).unwrap()), rows(&tbl));
+    }
+
+    #[test]
+    fn reloads_typed() {
+        let mut tbl = Table::new(["time", "msplayed", "shuffle", "song", "count", "flag", "when", "code"]);
+        tbl.insert([date("2023-08-27 22:44:00"), Field::Number(4991), Field::Bool(true), Field::String("1999".into()), Field::Number(3), Field::Bool(false), date("2020-01-01 00:00:00"), Field::String("007".into())]).unwrap();
+        tbl.insert([Field::Null, Field::Null, Field::Null, Field::Null, Field::Null, Field::Null, Field::Null, Field::Null]).unwrap();
+
+        let loaded = load(&write(&tbl, None)).unwrap();
+        assert_eq!(rows(&loaded), rows(&tbl));
+        assert_eq!(loaded.header, tbl.header);
+
+        // export columns keep their kind even when a value doesn't fit it
+        assert_eq!(error("msplayed\r\nabc\r\n"), "test.csv:2:1 (msplayed): msplayed expects a number, got 'abc'");
+
+        // a column of numbers too big to be one is text
+        assert_eq!(rows(&load("n\r\n99999999999999999999\r\n").unwrap()), vec![vec![Field::String("99999999999999999999".into())]]);
+    }
+
+    #[test]
+    fn zoned_times_reload_as_the_same_instant() {
+        let mut tbl = Table::new(["time"]);
+        tbl.insert([date("2023-08-27 22:44:00")]).unwrap();
+        tbl.insert([date("2023-01-10 10:00:00")]).unwrap();
+        tbl.insert([Field::Null]).unwrap();
+
+        let utc = write(&tbl, None);
+        assert_eq!(utc, "time\r\n2023-08-27 22:44:00\r\n2023-01-10 10:00:00\r\n\r\n");
+
+        let tz = TimeZone::Fixed(2 * 3600);
+        let text = write(&tbl.to_zone(&tz), Some(&tz));
+        assert_eq!(text, "time\r\n2023-08-28 00:44:00+02:00\r\n2023-01-10 12:00:00+02:00\r\n\r\n");
+        assert_eq!(rows(&load(&text).unwrap()), rows(&load(&utc).unwrap()));
+
+        let tz = TimeZone::Fixed(-(5 * 3600 + 30 * 60));
+        let text = write(&load(&utc).unwrap().to_zone(&tz), Some(&tz));
+        assert_eq!(text.lines().nth(1), Some("2023-08-27 17:14:00-05:30"));
+        assert_eq!(rows(&load(&text).unwrap()), rows(&load(&utc).unwrap()));
+
+        assert_eq!(rows(&load("time\r\n2023-08-27T22:44:00Z\r\n").unwrap()), vec![vec![date("2023-08-27 22:44:00")]]);
+    }
+
+    #[test]
+    fn points_at_bad_records() {
+        assert_eq!(error("a,b\r\n1,2\r\n\"x\r\ny\",2,3\r\n"), "test.csv:3:1: invalid csv: expected 2 fields, found 3");
+
+        assert_eq!(error("a,b\r\n1,\"2\r\n"), "test.csv:3:1: invalid csv: quoted field never ends");
+
+        assert_eq!(error("a,b\r\n\"1\"x,2\r\n"), "test.csv:2:5: invalid csv: expected ',' or a line break after a quoted field, found 'x'");
+
+        assert_eq!(error(""), "test.csv:1:1: invalid csv: no header");
+    }
+
+    #[test]
+    fn skips_byte_order_marks_and_blank_lines() {
+        let tbl = load("\u{feff}a,b\r\n\r\n1,2\n\n3,4").unwrap();
+        assert_eq!(tbl.header, vec![("a".to_owned(), 0), ("b".to_owned(), 1)]);
+        assert_eq!(rows(&tbl), vec![vec![Field::Number(1), Field::Number(2)], vec![Field::Number(3), Field::Number(4)]]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Read;
+
+use crate::error;
+
+use super::{
+    parse::{BigBuilder, BuilderTrait, Schema, SmallBuilder, BIG_HISTORY_SCHEMA, SMALL_HISTORY_SCHEMA},
+    parse_arguments::{State, Value},
+    table::Table,
+};
+
+/// The streaming history formats Spotify hands out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// `Streaming_History_Audio_*.json` from the extended streaming history
+    Extended,
+    /// `StreamingHistory*.json` from the regular account data
+    Account,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Extended, ExportFormat::Account];
+
+    pub fn schema(&self) -> &'static Schema {
+        match self {
+            ExportFormat::Extended => &BIG_HISTORY_SCHEMA,
+            ExportFormat::Account => &SMALL_HISTORY_SCHEMA,
+        }
+    }
+
+    pub fn builder<'a>(&self, tbl: &'a mut Table) -> Box<dyn BuilderTrait + 'a> {
+        match self {
+            ExportFormat::Extended => Box::new(BigBuilder::new(tbl)),
+            ExportFormat::Account => Box::new(SmallBuilder::new(tbl)),
+        }
+    }
+
+    /// Guesses the format from the file name alone.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if !name.ends_with(".json") {
+            return None;
+        }
+
+        if name.starts_with("Streaming_History_") || name.starts_with("endsong") {
+            Some(ExportFormat::Extended)
+        } else if name.starts_with("StreamingHistory") {
+            Some(ExportFormat::Account)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a record with `keys` is a play of this format. It has to say
+    /// when and for how long, sharing some other key isn't enough: the search
+    /// queries and user data in the same archive have `platform` or `username`.
+    fn is_play(&self, keys: &[String]) -> bool {
+        let schema = self.schema();
+        ["time", "msplayed"].iter().all(|column| {
+            schema.keys.iter().any(|(key, col, _)| col == column && keys.iter().any(|k| k == key))
+        })
+    }
+
+    /// Picks the format whose schema knows the most keys of the first record,
+    /// out of those it's a play of. Empty exports, files that aren't a list of
+    /// records and records of anything but plays give `None`.
+    pub fn from_content<R: Read>(reader: R) -> error::Result<Option<Self>> {
+        let keys = first_record_keys(reader)?;
+
+        Ok(Self::ALL
+            .into_iter()
+            .filter(|format| format.is_play(&keys))
+            .max_by_key(|format| keys.iter().filter(|k| format.schema().key_of(k).is_some()).count()))
+    }
+}
+
+fn first_record_keys<R: Read>(reader: R) -> error::Result<Vec<String>> {
+    let mut state = State::new(reader);
+    let mut keys = Vec::new();
+
+    state.skip_bom()?;
+    state.skip_whitespace()?;
+
+    if let Some(b'[') = state.peek()? {
+        state.pop()?;
+        state.skip_whitespace()?;
+    }
+
+    if let Some(b'{') = state.peek()? {
+        state.walk_object(|state, key| -> error::Result<()> {
+            keys.push(key);
+            state.parse_value().map(|_: Value| ())?;
+            Ok(())
+        })?;
+    }
+
+    Ok(keys)
+}
@@ -0,0 +1,256 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::{Add, Div, Mul, Sub},
+};
+
+use super::{
+    column::Column,
+    parse::DateTime,
+    predicate::{Bound, Predicate},
+    table::{DataErrors, Field, Table},
+};
+
+/// A calendar bucket of a date, for looking at listening over time. All are
+/// numbers except `Date`, which is the date at midnight, and `Week`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bucket {
+    Year,
+    /// 1 to 12
+    Month,
+    /// ISO, 1 for Monday through 7 for Sunday
+    Weekday,
+    /// 0 to 23
+    Hour,
+    Date,
+    /// ISO week with the year it belongs to, like `2020-W53`, which sorts
+    /// as text and keeps the weeks of different years apart
+    Week,
+}
+
+impl Bucket {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "year" => Bucket::Year,
+            "month" => Bucket::Month,
+            "weekday" => Bucket::Weekday,
+            "hour" => Bucket::Hour,
+            "date" => Bucket::Date,
+            "week" => Bucket::Week,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bucket::Year => "year",
+            Bucket::Month => "month",
+            Bucket::Weekday => "weekday",
+            Bucket::Hour => "hour",
+            Bucket::Date => "date",
+            Bucket::Week => "week",
+        }
+    }
+
+    pub fn apply(&self, date: &DateTime) -> Field {
+        match self {
+            Bucket::Year => Field::Number(date.year as u64),
+            Bucket::Month => Field::Number(date.month as u64),
+            Bucket::Weekday => Field::Number(date.weekday() as u64),
+            Bucket::Hour => Field::Number(date.hour as u64),
+            Bucket::Date => Field::Date(DateTime { hour: 0, minute: 0, second: 0, ..*date }),
+            Bucket::Week => {
+                let (year, week) = date.iso_week();
+                Field::String(format!("{year}-W{week:02}"))
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl ArithOp {
+    /// `None` when the result isn't a `u64`: below zero, overflowing or divided by zero.
+    fn apply(&self, a: u64, b: u64) -> Option<u64> {
+        match self {
+            ArithOp::Add => a.checked_add(b),
+            ArithOp::Sub => a.checked_sub(b),
+            ArithOp::Mul => a.checked_mul(b),
+            ArithOp::Div => a.checked_div(b),
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+        }
+    }
+}
+
+/// A value computed from the columns of a row, see `Table::with_column`, e.g.
+/// `Expr::col("msplayed") / Expr::lit(60_000u64)`. A null anywhere makes the
+/// whole expression null, except inside a `Predicate`, which is always true or false.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Field),
+    Bucket(Bucket, Box<Expr>),
+    /// integer arithmetic on numbers, null where the result isn't a `u64`
+    Arith(ArithOp, Box<Expr>, Box<Expr>),
+    /// text of both sides joined, numbers and dates as they're displayed
+    Concat(Box<Expr>, Box<Expr>),
+    /// `Field::Bool` of whether the row matches, evaluated like `Table::filter` does
+    Predicate(Box<Predicate>),
+}
+
+impl Expr {
+    pub fn col(name: &str) -> Expr {
+        Expr::Column(name.to_owned())
+    }
+
+    pub fn lit<F: Into<Field>>(value: F) -> Expr {
+        Expr::Literal(value.into())
+    }
+
+    pub fn bucket(bucket: Bucket, column: &str) -> Expr {
+        Expr::Bucket(bucket, Box::new(Expr::col(column)))
+    }
+
+    pub fn concat(self, other: Expr) -> Expr {
+        Expr::Concat(Box::new(self), Box::new(other))
+    }
+
+    /// Resolves column names once so evaluating a row is only index lookups.
+    pub(crate) fn bind(&self, table: &Table) -> Result<BoundExpr, DataErrors> {
+        Ok(match self {
+            Expr::Column(c) => BoundExpr::Column(table.get_col(c)?),
+            Expr::Literal(f) => BoundExpr::Literal(f.clone()),
+            Expr::Bucket(b, e) => BoundExpr::Bucket(*b, Box::new(e.bind(table)?)),
+            Expr::Arith(op, a, b) => BoundExpr::Arith(*op, Box::new(a.bind(table)?), Box::new(b.bind(table)?)),
+            Expr::Concat(a, b) => BoundExpr::Concat(Box::new(a.bind(table)?), Box::new(b.bind(table)?)),
+            Expr::Predicate(p) => BoundExpr::Predicate(p.bind(table)?),
+        })
+    }
+}
+
+impl From<Predicate> for Expr {
+    fn from(value: Predicate) -> Self {
+        Expr::Predicate(Box::new(value))
+    }
+}
+
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(self, rhs: Expr) -> Expr {
+        Expr::Arith(ArithOp::Add, Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Sub for Expr {
+    type Output = Expr;
+
+    fn sub(self, rhs: Expr) -> Expr {
+        Expr::Arith(ArithOp::Sub, Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, rhs: Expr) -> Expr {
+        Expr::Arith(ArithOp::Mul, Box::new(self), Box::new(rhs))
+    }
+}
+
+impl Div for Expr {
+    type Output = Expr;
+
+    fn div(self, rhs: Expr) -> Expr {
+        Expr::Arith(ArithOp::Div, Box::new(self), Box::new(rhs))
+    }
+}
+
+/// How the expression is written in a query, which is also the default
+/// header of its column, e.g. `year(time)`.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // operands that are operations themselves get parentheses
+        let operand = |e: &Expr| match e {
+            Expr::Arith(..) | Expr::Concat(..) | Expr::Predicate(_) => format!("({e})"),
+            _ => e.to_string(),
+        };
+
+        match self {
+            Expr::Column(c) => f.write_str(c),
+            Expr::Literal(Field::String(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(l) => write!(f, "{l}"),
+            Expr::Bucket(b, e) => write!(f, "{}({e})", b.name()),
+            Expr::Arith(op, a, b) => write!(f, "{} {} {}", operand(a), op.symbol(), operand(b)),
+            Expr::Concat(a, b) => write!(f, "{} || {}", operand(a), operand(b)),
+            Expr::Predicate(p) => write!(f, "{p}"),
+        }
+    }
+}
+
+/// `Expr` with columns resolved to indices.
+pub(crate) enum BoundExpr {
+    Column(usize),
+    Literal(Field),
+    Bucket(Bucket, Box<BoundExpr>),
+    Arith(ArithOp, Box<BoundExpr>, Box<BoundExpr>),
+    Concat(Box<BoundExpr>, Box<BoundExpr>),
+    Predicate(Bound),
+}
+
+impl BoundExpr {
+    pub(crate) fn eval(&self, cols: &[Column], row: usize) -> Result<Field, DataErrors> {
+        Ok(match self {
+            BoundExpr::Column(col) => cols[*col].get(row),
+            BoundExpr::Literal(f) => f.clone(),
+            BoundExpr::Bucket(bucket, e) => match e.eval(cols, row)? {
+                Field::Date(date) => bucket.apply(&date),
+                Field::Null => Field::Null,
+                other => return Err(DataErrors::WrongType(format!("{} expects dates, got '{other}'", bucket.name()))),
+            },
+            BoundExpr::Arith(op, a, b) => match (a.eval(cols, row)?, b.eval(cols, row)?) {
+                (Field::Number(a), Field::Number(b)) => op.apply(a, b).into(),
+                (Field::Null, _) | (_, Field::Null) => Field::Null,
+                (a, b) => return Err(DataErrors::WrongType(format!("{} expects numbers, got '{a}' and '{b}'", op.symbol()))),
+            },
+            BoundExpr::Concat(a, b) => match (a.eval(cols, row)?, b.eval(cols, row)?) {
+                (Field::Null, _) | (_, Field::Null) => Field::Null,
+                (a, b) => Field::String(format!("{a}{b}")),
+            },
+            BoundExpr::Predicate(p) => Field::Bool(p.eval(cols, row)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn week(year: u16, month: u8, day: u8) -> Field {
+        Bucket::Week.apply(&DateTime { day, month, year, minute: 0, hour: 0, second: 0 })
+    }
+
+    #[test]
+    fn week_keeps_its_iso_year() {
+        assert_eq!(week(2020, 6, 15), Field::String("2020-W25".to_owned()));
+        // Thursday 2020-12-31 is in week 53 of 2020, Friday 2021-01-01 too
+        assert_eq!(week(2020, 12, 31), Field::String("2020-W53".to_owned()));
+        assert_eq!(week(2021, 1, 1), Field::String("2020-W53".to_owned()));
+        // Monday 2024-12-30 already is in week 1 of 2025
+        assert_eq!(week(2024, 12, 30), Field::String("2025-W01".to_owned()));
+        assert_ne!(week(2019, 1, 9), week(2020, 1, 8));
+    }
+}
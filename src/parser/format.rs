@@ -0,0 +1,246 @@
+use super::{
+    parse::DateTime,
+    table::{Field, Table},
+};
+
+/// How the fields of a column are shown. Only fields of the matching kind are
+/// affected, everything else, nulls included, shows as usual.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldFormat {
+    /// `Field`'s own Display
+    Raw,
+    /// milliseconds as `5s`, `3m 12s` or `41h 07m`
+    Duration,
+    /// thousands separated, `12,345`
+    Number,
+    /// share of `total` with one decimal, `12.5%`
+    Percent { total: u64 },
+    /// dates by a pattern, see `format_date`
+    Date(String),
+}
+
+pub const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+pub const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+impl FieldFormat {
+    /// `raw`, `duration`, `number`, `percent`, or a date pattern which is
+    /// anything with a `%` in it. The total of `percent` is left for the
+    /// caller to fill in.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "raw" => FieldFormat::Raw,
+            "duration" => FieldFormat::Duration,
+            "number" => FieldFormat::Number,
+            "percent" => FieldFormat::Percent { total: 0 },
+            pattern if pattern.contains('%') => FieldFormat::Date(pattern.to_owned()),
+            _ => return None,
+        })
+    }
+
+    /// What a column is best shown as going by its name: play time and its
+    /// aggregates as durations, counts with separators.
+    pub fn guess(column: &str) -> FieldFormat {
+        let inner = column.split_once('(').map_or(column, |(_, rest)| rest.trim_end_matches(')'));
+
+        match (column, inner) {
+            (_, "msplayed") if !column.starts_with("count") => FieldFormat::Duration,
+            ("count", _) | ("plays", _) => FieldFormat::Number,
+            (c, _) if c.starts_with("count_distinct(") => FieldFormat::Number,
+            _ => FieldFormat::Raw,
+        }
+    }
+}
+
+/// `5s`, `3m 12s` or `41h 07m`, rounded down.
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds:0>2}s"),
+        _ => format!("{hours}h {minutes:0>2}m"),
+    }
+}
+
+/// `1234567` as `1,234,567`.
+pub fn format_number(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+/// strftime style: `%Y` year, `%m` month, `%d` day, `%H` hour, `%M` minute,
+/// `%S` second, `%b` month name, `%a` weekday name, `%u` ISO weekday,
+/// `%V` ISO week, `%j` day of the year and `%%` for a `%`. Anything else is
+/// kept as written.
+pub fn format_date(date: &DateTime, pattern: &str) -> String {
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('Y') => out += &date.year.to_string(),
+            Some('m') => out += &format!("{:0>2}", date.month),
+            Some('d') => out += &format!("{:0>2}", date.day),
+            Some('H') => out += &format!("{:0>2}", date.hour),
+            Some('M') => out += &format!("{:0>2}", date.minute),
+            Some('S') => out += &format!("{:0>2}", date.second),
+            Some('b') => out += MONTHS[(date.month as usize).clamp(1, 12) - 1],
+            Some('a') => out += WEEKDAYS[date.weekday() as usize - 1],
+            Some('u') => out += &date.weekday().to_string(),
+            Some('V') => out += &format!("{:0>2}", date.iso_week().1),
+            Some('j') => out += &format!("{:0>3}", date.day_of_year()),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+
+    out
+}
+
+impl Field {
+    pub fn format(&self, format: &FieldFormat) -> String {
+        match (format, self) {
+            (FieldFormat::Duration, Field::Number(ms)) => format_duration(*ms),
+            (FieldFormat::Number, Field::Number(n)) => format_number(*n),
+            (FieldFormat::Percent { total: 0 }, Field::Number(_)) => "-".to_owned(),
+            (FieldFormat::Percent { total }, Field::Number(n)) => format!("{:.1}%", *n as f64 * 100.0 / *total as f64),
+            (FieldFormat::Date(pattern), Field::Date(d)) => format_date(d, pattern),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// A format for each column of `tbl`, in header order. Those in `explicit` win,
+/// then guesses by name when `guess` is set, then `date_format` for dates.
+/// Percentages are of their column's total. Entries in `explicit` for columns
+/// `tbl` doesn't have are left out, so one `--display` can go with any command.
+pub fn column_formats(tbl: &Table, explicit: &[(String, FieldFormat)], date_format: Option<&str>, guess: bool) -> Vec<FieldFormat> {
+    tbl.header
+        .iter()
+        .map(|(name, col)| {
+            let chosen = explicit.iter().rev().find(|(c, _)| c == name).map(|(_, f)| f.clone());
+            let format = match (chosen, date_format) {
+                (Some(f), _) => f,
+                (None, _) if guess && FieldFormat::guess(name) != FieldFormat::Raw => FieldFormat::guess(name),
+                (None, Some(pattern)) => FieldFormat::Date(pattern.to_owned()),
+                (None, None) => FieldFormat::Raw,
+            };
+
+            match format {
+                FieldFormat::Percent { .. } => FieldFormat::Percent {
+                    total: (0..tbl.len()).map(|row| match tbl.get(row, *col) {
+                        Field::Number(n) => n,
+                        _ => 0,
+                    }).sum(),
+                },
+                other => other,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn date(s: &str) -> DateTime {
+        DateTime::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(999), "0s");
+        assert_eq!(format_duration(59_999), "59s");
+        assert_eq!(format_duration(60_000), "1m 00s");
+        assert_eq!(format_duration(192_500), "3m 12s");
+        assert_eq!(format_duration(3_599_999), "59m 59s");
+        assert_eq!(format_duration(3_600_000), "1h 00m");
+        assert_eq!(format_duration(86_399_000), "23h 59m");
+        assert_eq!(format_duration(147_720_000), "41h 02m");
+        assert_eq!(format_duration(1_000 * 3600 * 24 * 400), "9600h 00m");
+        assert_eq!(format_duration(u64::MAX), "5124095576030h 25m");
+    }
+
+    #[test]
+    fn thousands_separators() {
+        assert_eq!(format_number(0), "0");
+        assert_eq!(format_number(999), "999");
+        assert_eq!(format_number(1_000), "1,000");
+        assert_eq!(format_number(12_345), "12,345");
+        assert_eq!(format_number(123_456), "123,456");
+        assert_eq!(format_number(1_234_567), "1,234,567");
+        assert_eq!(format_number(u64::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn dates() {
+        let d = date("2023-01-01 09:05:07");
+        assert_eq!(format_date(&d, "%Y-%m-%d %H:%M:%S"), "2023-01-01 09:05:07");
+        // the first of January 2023 is a Sunday, in the last ISO week of 2022
+        assert_eq!(format_date(&d, "%a %d %b, day %j, week %V, weekday %u"), "Sun 01 Jan, day 001, week 52, weekday 7");
+        assert_eq!(format_date(&date("2024-12-31 23:59:59"), "%j %V %b"), "366 01 Dec");
+        assert_eq!(format_date(&d, "100%% %q %"), "100% %q %");
+        assert_eq!(format_date(&d, "Y-m-d"), "Y-m-d");
+    }
+
+    #[test]
+    fn fields_by_format() {
+        assert_eq!(Field::Number(192_500).format(&FieldFormat::Duration), "3m 12s");
+        assert_eq!(Field::Number(12_345).format(&FieldFormat::Number), "12,345");
+        assert_eq!(Field::Number(1).format(&FieldFormat::Percent { total: 8 }), "12.5%");
+        assert_eq!(Field::Number(1).format(&FieldFormat::Percent { total: 0 }), "-");
+        assert_eq!(Field::Date(date("2023-08-27 22:44:00")).format(&FieldFormat::Date("%d/%m".into())), "27/08");
+
+        // other kinds and nulls show as usual
+        assert_eq!(Field::String("Ben".into()).format(&FieldFormat::Duration), "Ben");
+        assert_eq!(Field::Null.format(&FieldFormat::Number), Field::Null.to_string());
+        assert_eq!(Field::Number(12_345).format(&FieldFormat::Date("%Y".into())), "12345");
+    }
+
+    #[test]
+    fn names_and_guesses() {
+        assert_eq!(FieldFormat::from_name("duration"), Some(FieldFormat::Duration));
+        assert_eq!(FieldFormat::from_name("%Y"), Some(FieldFormat::Date("%Y".into())));
+        assert_eq!(FieldFormat::from_name("minutes"), None);
+
+        assert_eq!(FieldFormat::guess("msplayed"), FieldFormat::Duration);
+        assert_eq!(FieldFormat::guess("sum(msplayed)"), FieldFormat::Duration);
+        assert_eq!(FieldFormat::guess("count_distinct(msplayed)"), FieldFormat::Number);
+        assert_eq!(FieldFormat::guess("count"), FieldFormat::Number);
+        assert_eq!(FieldFormat::guess("song"), FieldFormat::Raw);
+    }
+
+    #[test]
+    fn formats_per_column() {
+        let mut tbl = Table::new(["artist", "msplayed", "time"]);
+        tbl.insert([Field::String("a".into()), Field::Number(3), Field::Date(date("2023-08-27 22:44:00"))]).unwrap();
+        tbl.insert([Field::String("b".into()), Field::Number(1), Field::Null]).unwrap();
+
+        let explicit = [("msplayed".to_owned(), FieldFormat::Percent { total: 0 }), ("absent".to_owned(), FieldFormat::Number)];
+        assert_eq!(column_formats(&tbl, &explicit, Some("%Y"), true), [FieldFormat::Date("%Y".into()), FieldFormat::Percent { total: 4 }, FieldFormat::Date("%Y".into())]);
+        assert_eq!(column_formats(&tbl, &[], None, true), [FieldFormat::Raw, FieldFormat::Duration, FieldFormat::Raw]);
+        assert_eq!(column_formats(&tbl, &[], None, false), [FieldFormat::Raw, FieldFormat::Raw, FieldFormat::Raw]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+/// Candidates scoring below this aren't worth suggesting
+pub const MIN_SCORE: f64 = 0.4;
+
+/// Lowercased with runs of whitespace made single spaces.
+fn normalize(s: &str) -> Vec<char> {
+    s.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase().chars().collect()
+}
+
+fn trigrams(s: &[char]) -> HashSet<[char; 3]> {
+    // padding lets the first and last chars weigh as much as the middle ones
+    let padded: Vec<char> = [' ', ' '].into_iter().chain(s.iter().copied()).chain([' ']).collect();
+    padded.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + (ca != cb) as usize;
+            current[j + 1] = substitute.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+fn contains(haystack: &[char], needle: &[char]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// How alike `a` and `b` are ignoring case, from 0 to 1. The best of trigram
+/// overlap, which forgives reordered words, edit distance, which forgives
+/// typos, and one containing the other, which forgives a missing `& friends`.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let (ta, tb) = (trigrams(&a), trigrams(&b));
+    let dice = 2.0 * ta.intersection(&tb).count() as f64 / (ta.len() + tb.len()) as f64;
+
+    let longest = a.len().max(b.len());
+    let edit = 1.0 - levenshtein(&a, &b) as f64 / longest as f64;
+
+    let (short, long) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+    let substring = match contains(long, short) {
+        true => 0.2 + 0.8 * short.len() as f64 / long.len() as f64,
+        false => 0.0,
+    };
+
+    dice.max(edit).max(substring)
+}
+
+/// The `limit` candidates most like `query`, best first, leaving out those
+/// under `MIN_SCORE`. Ties keep the order of `candidates`.
+pub fn rank<'a, I: IntoIterator<Item = &'a str>>(query: &str, candidates: I, limit: usize) -> Vec<(&'a str, f64)> {
+    let mut scored: Vec<(&str, f64)> = candidates
+        .into_iter()
+        .map(|c| (c, similarity(query, c)))
+        .filter(|(_, score)| *score >= MIN_SCORE)
+        .collect();
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(limit);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarity_forgives_case_typos_and_word_order() {
+        assert_eq!(similarity("The  Beatles", "the beatles"), 1.0);
+        assert!(similarity("Beatles", "Beatels") > 0.7);
+        assert!(similarity("Simon Garfunkel", "Garfunkel Simon") > 0.6);
+        assert!(similarity("Mumford", "Mumford & Sons") > 0.6);
+        assert_eq!(similarity("", "abc"), 0.0);
+        assert!(similarity("abc", "xyz") < MIN_SCORE);
+    }
+
+    #[test]
+    fn rank_orders_best_first() {
+        let candidates = ["Metallica", "Metronomy", "The Beatles", "Beatles Tribute", "Beach House"];
+        let ranked: Vec<&str> = rank("beatles", candidates, 10).into_iter().map(|(c, _)| c).collect();
+
+        assert_eq!(ranked[..2], ["The Beatles", "Beatles Tribute"]);
+        assert!(!ranked.contains(&"Metallica"));
+    }
+
+    #[test]
+    fn rank_keeps_the_threshold_and_limit() {
+        let candidates = ["abcdef", "abcdxx", "abxxxx", "xxxxxx"];
+
+        let ranked = rank("abcdef", candidates, 10);
+        assert!(ranked.iter().all(|(_, score)| *score >= MIN_SCORE));
+        assert!(ranked.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(ranked.iter().map(|(c, _)| *c).collect::<Vec<_>>(), ["abcdef", "abcdxx"]);
+
+        assert_eq!(rank("abcdef", candidates, 1), [("abcdef", 1.0)]);
+        assert!(rank("abcdef", ["zzzzzz"], 10).is_empty());
+    }
+
+    #[test]
+    fn rank_keeps_the_order_of_ties() {
+        let ranked: Vec<&str> = rank("abc", ["abd", "abe", "abf"], 10).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(ranked, ["abd", "abe", "abf"]);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{LazyLock, Mutex, OnceLock, PoisonError},
+};
+
+/// Slots in the first bucket of `STRINGS`, every next bucket has twice as many
+const FIRST_BUCKET: usize = 64;
+/// Enough buckets for every `u32`
+const BUCKETS: usize = 26;
+
+/// A string stored once for the whole process, see `Symbol::intern`. Equal
+/// strings get equal symbols, so comparing or hashing one is an integer op.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// Every interned string by symbol. Buckets are only ever added and slots
+/// only ever set once, so reading takes no lock.
+static STRINGS: [OnceLock<Box<[OnceLock<&'static str>]>>; BUCKETS] = [const { OnceLock::new() }; BUCKETS];
+
+/// Symbol of every interned string, taken to intern a new one.
+static INDEX: LazyLock<Mutex<HashMap<&'static str, Symbol>>> = LazyLock::new(Default::default);
+
+thread_local! {
+    /// The part of `INDEX` this thread has seen, so parsing threads don't
+    /// queue up on the lock for strings they've already interned.
+    static SEEN: RefCell<HashMap<&'static str, Symbol>> = RefCell::new(HashMap::new());
+}
+
+/// Bucket and slot of symbol `id`.
+fn slot(id: usize) -> (usize, usize) {
+    let n = id + FIRST_BUCKET;
+    let bucket = (n.ilog2() - FIRST_BUCKET.ilog2()) as usize;
+    (bucket, n - (FIRST_BUCKET << bucket))
+}
+
+impl Symbol {
+    /// The symbol of `s`, storing it if it's new. Interned strings live until
+    /// the process ends.
+    pub fn intern(s: &str) -> Symbol {
+        if let Some(symbol) = SEEN.with_borrow(|seen| seen.get(s).copied()) {
+            return symbol;
+        }
+
+        let mut index = INDEX.lock().unwrap_or_else(PoisonError::into_inner);
+        let symbol = match index.get(s) {
+            Some(symbol) => *symbol,
+            None => {
+                let id = index.len();
+                let stored: &'static str = Box::leak(s.into());
+
+                let (bucket, offset) = slot(id);
+                let slots = STRINGS[bucket].get_or_init(|| (0..FIRST_BUCKET << bucket).map(|_| OnceLock::new()).collect());
+                let _ = slots[offset].set(stored);
+
+                let symbol = Symbol(u32::try_from(id).expect("out of symbols"));
+                index.insert(stored, symbol);
+                symbol
+            }
+        };
+        drop(index);
+
+        SEEN.with_borrow_mut(|seen| seen.insert(symbol.as_str(), symbol));
+        symbol
+    }
+
+    /// The symbol of `s` if it has been interned. Nothing can equal a string
+    /// that hasn't.
+    pub fn lookup(s: &str) -> Option<Symbol> {
+        SEEN.with_borrow(|seen| seen.get(s).copied())
+            .or_else(|| INDEX.lock().unwrap_or_else(PoisonError::into_inner).get(s).copied())
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let (bucket, offset) = slot(self.0 as usize);
+        STRINGS[bucket].get().and_then(|slots| slots[offset].get()).expect("symbols only come from intern")
+    }
+
+    /// Dense from 0 in the order strings were first interned.
+    pub fn id(self) -> u32 {
+        self.0
+    }
+}
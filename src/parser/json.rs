@@ -0,0 +1,139 @@
+use std::io::{self, Write};
+
+use super::{
+    format::format_date,
+    parse_arguments::Value,
+    table::{Field, Table},
+    tz::{format_offset, TimeZone},
+};
+
+/// ISO 8601 with the offset of `zone`, the zone the table's times are in,
+/// e.g. `2023-08-27T22:44:00Z` or `2023-08-27T22:44:00+02:00`.
+fn iso8601(field: &Field, zone: Option<&TimeZone>) -> String {
+    let Field::Date(d) = field else { return field.to_string() };
+
+    let offset = zone.map_or(0, |tz| tz.offset_at_local(d.timestamp()));
+    let suffix = match offset {
+        0 => "Z".to_owned(),
+        _ => format_offset(offset),
+    };
+
+    format_date(d, "%Y-%m-%dT%H:%M:%S") + &suffix
+}
+
+impl Table {
+    fn json_object(&self, row: usize, zone: Option<&TimeZone>) -> Value {
+        Value::Object(
+            self.header
+                .iter()
+                .map(|(name, col)| {
+                    let value = match self.get(row, *col) {
+                        Field::Null => Value::Null,
+                        Field::String(s) => Value::String(s),
+                        Field::Number(n) => Value::Number(n.to_string()),
+                        Field::Bool(b) => Value::Bool(b),
+                        date => Value::String(iso8601(&date, zone)),
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+        )
+    }
+
+    /// Writes an array with an object per row, keyed by the header names.
+    /// Numbers, bools and nulls keep their JSON types, dates are ISO 8601
+    /// strings with the offset of `zone`, UTC when it's `None`.
+    pub fn write_json<W: Write>(&self, out: &mut W, zone: Option<&TimeZone>) -> io::Result<()> {
+        if self.is_empty() {
+            return writeln!(out, "[]");
+        }
+
+        writeln!(out, "[")?;
+        for row in 0..self.len() {
+            let sep = if row + 1 < self.len() { "," } else { "" };
+            writeln!(out, "  {}{sep}", self.json_object(row, zone))?;
+        }
+        writeln!(out, "]")
+    }
+
+    /// Like `write_json` but a bare object per line, so readers can start on
+    /// the first row before the last is written.
+    pub fn write_ndjson<W: Write>(&self, out: &mut W, zone: Option<&TimeZone>) -> io::Result<()> {
+        for row in 0..self.len() {
+            writeln!(out, "{}", self.json_object(row, zone))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::parser::{parse::DateTime, parse_arguments::State};
+
+    fn date(s: &str) -> Field {
+        Field::Date(DateTime::from_str(s).unwrap())
+    }
+
+    fn json(tbl: &Table, zone: Option<&TimeZone>) -> String {
+        let mut out = Vec::new();
+        tbl.write_json(&mut out, zone).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn ndjson(tbl: &Table, zone: Option<&TimeZone>) -> String {
+        let mut out = Vec::new();
+        tbl.write_ndjson(&mut out, zone).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn keeps_types_and_nulls() {
+        let mut tbl = Table::new(["song", "msplayed", "shuffle", "time"]);
+        tbl.insert([Field::String("Ben".into()), Field::Number(4991), Field::Bool(true), date("2023-08-27 22:44:00")]).unwrap();
+        tbl.insert([Field::Null, Field::Null, Field::Null, Field::Null]).unwrap();
+        tbl.insert([Field::String("".into()), Field::Number(0), Field::Bool(false), Field::Null]).unwrap();
+
+        assert_eq!(
+            json(&tbl, None),
+            "[\n  {\"song\":\"Ben\",\"msplayed\":4991,\"shuffle\":true,\"time\":\"2023-08-27T22:44:00Z\"},\n  {\"song\":null,\"msplayed\":null,\"shuffle\":null,\"time\":null},\n  {\"song\":\"\",\"msplayed\":0,\"shuffle\":false,\"time\":null}\n]\n"
+        );
+        assert_eq!(json(&Table::new(["song"]), None), "[]\n");
+        assert_eq!(ndjson(&Table::new(["song"]), None), "");
+    }
+
+    #[test]
+    fn escapes_strings_and_names() {
+        let mut tbl = Table::new(["say \"what\""]);
+        let text = "quote \" backslash \\ slash / newline \n return \r tab \t bell \u{7} Björk 🎵";
+        tbl.insert([Field::String(text.into())]).unwrap();
+
+        let line = ndjson(&tbl, None);
+        assert_eq!(line, "{\"say \\\"what\\\"\":\"quote \\\" backslash \\\\ slash / newline \\n return \\r tab \\t bell \\u0007 Björk 🎵\"}\n");
+
+        // and reads back as it was
+        let Value::Object(entries) = State::new(line.as_bytes()).parse_value().unwrap() else { panic!("not an object") };
+        assert_eq!(entries, [("say \"what\"".to_owned(), Value::String(text.into()))]);
+    }
+
+    #[test]
+    fn offsets_of_the_zone() {
+        let mut tbl = Table::new(["time"]);
+        tbl.insert([date("2023-08-27 22:44:00")]).unwrap();
+
+        let tz = TimeZone::Fixed(2 * 3600);
+        assert_eq!(ndjson(&tbl.to_zone(&tz), Some(&tz)), "{\"time\":\"2023-08-28T00:44:00+02:00\"}\n");
+
+        let mut tbl = Table::new(["time"]);
+        tbl.insert([date("2023-08-27 22:44:00")]).unwrap();
+        let tz = TimeZone::Fixed(-(9 * 3600 + 30 * 60));
+        assert_eq!(ndjson(&tbl.to_zone(&tz), Some(&tz)), "{\"time\":\"2023-08-27T13:14:00-09:30\"}\n");
+
+        // a zone at UTC is written like no zone
+        let mut tbl = Table::new(["time"]);
+        tbl.insert([date("2023-08-27 22:44:00")]).unwrap();
+        assert_eq!(ndjson(&tbl, Some(&TimeZone::Fixed(0))), ndjson(&tbl, None));
+    }
+}
@@ -1,6 +1,30 @@
+pub mod aggregate;
+
+pub mod column;
+
+pub mod csv;
+
+pub mod detect;
+
+pub mod expr;
+
+pub mod format;
+
+pub mod fuzzy;
+
+pub mod intern;
+
+pub mod json;
+
 pub mod parse;
-//pub mod parse_arguments;
+pub mod parse_arguments;
+
+pub mod predicate;
+
+pub mod regex;
 
 pub mod table;
 
+pub mod tz;
+
 pub mod utils;
\ No newline at end of file
@@ -1,9 +1,57 @@
 use std::{
-    cmp::Ordering, ffi::OsString, fmt::{self, Debug, Display, Formatter}, fs::File, io::{self, BufRead, BufReader}, num::{IntErrorKind, ParseIntError}, path::PathBuf, str::FromStr
+    cmp::Ordering, collections::BTreeMap, ffi::OsString, fmt::{self, Debug, Display, Formatter}, fs::File, io::{self, BufReader, Read}, num::{IntErrorKind, ParseIntError}, ops::{Add, Sub}, path::PathBuf, str::FromStr, time::Duration
 };
 
-use super::table::{Field, Table};
+use crate::error::{self, Error, ErrorKind};
 
+use super::{parse_arguments::{Position, State, Value}, table::{DataErrors, Field, Table}, tz::TimeZone};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date, negative before it.
+pub(crate) const fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    // the year is shifted to start in March so the leap day is its last day
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of `days_from_civil`.
+const fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+pub const fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+pub const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A wall clock time, to the second. The exports are in UTC, `to_zone` gives
+/// the local time somewhere else.
 #[derive(Copy, Clone, PartialEq, Hash, Eq)]
 pub struct DateTime {
     pub day: u8,
@@ -11,21 +59,125 @@ pub struct DateTime {
     pub year: u16,
     pub minute: u8,
     pub hour: u8,
+    pub second: u8,
 }
 
 impl DateTime {
-    pub const fn unix_like(&self) -> u64 {
-        let minute: u64 = 1000 * 60;
-        let hour: u64 = minute * 60;
-        let day: u64 = hour * 24;
-        let month: u64 = day * 30;
-        let year: u64 = day * 365;
+    /// Checks every part is in range, so `2021-02-29` is an error.
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Result<Self, DateTimeError> {
+        if !(1..=12).contains(&month) {
+            return Err(DateTimeError::OutOfRange("month"));
+        }
+        if day == 0 || day > days_in_month(year, month) {
+            return Err(DateTimeError::OutOfRange("day"));
+        }
+        if hour > 23 {
+            return Err(DateTimeError::OutOfRange("hour"));
+        }
+        if minute > 59 {
+            return Err(DateTimeError::OutOfRange("minute"));
+        }
+        if second > 59 {
+            return Err(DateTimeError::OutOfRange("second"));
+        }
+
+        Ok(DateTime { day, month, year, minute, hour, second })
+    }
+
+    /// Seconds since 1970-01-01 00:00:00 UTC.
+    pub const fn timestamp(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month, self.day) * SECS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    /// `None` when the year doesn't fit.
+    pub fn from_timestamp(secs: i64) -> Option<Self> {
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let time = secs.rem_euclid(SECS_PER_DAY);
+
+        Some(DateTime {
+            day,
+            month,
+            year: year.try_into().ok()?,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        })
+    }
+
+    /// The wall clock time in `tz` at this UTC time.
+    pub fn to_zone(&self, tz: &TimeZone) -> Self {
+        let timestamp = self.timestamp();
+        Self::from_timestamp(timestamp + tz.offset_at(timestamp) as i64).unwrap_or(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let secs: i64 = duration.as_secs().try_into().ok()?;
+        Self::from_timestamp(self.timestamp().checked_add(secs)?)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        let secs: i64 = duration.as_secs().try_into().ok()?;
+        Self::from_timestamp(self.timestamp().checked_sub(secs)?)
+    }
+
+    /// Time from `earlier` to `self`, `None` if `earlier` is later.
+    pub fn duration_since(&self, earlier: &DateTime) -> Option<Duration> {
+        let secs = self.timestamp() - earlier.timestamp();
+        Some(Duration::from_secs(secs.try_into().ok()?))
+    }
+
+    /// ISO day of the week, 1 for Monday through 7 for Sunday.
+    pub const fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((days_from_civil(self.year as i64, self.month, self.day) + 3).rem_euclid(7) + 1) as u8
+    }
+
+    /// 1 for January 1st.
+    pub const fn day_of_year(&self) -> u16 {
+        (days_from_civil(self.year as i64, self.month, self.day) - days_from_civil(self.year as i64, 1, 1) + 1) as u16
+    }
+
+    /// ISO 8601 week and the year it belongs to, which differs from `year`
+    /// for the first and last few days of some years.
+    pub fn iso_week(&self) -> (u16, u8) {
+        let week = (self.day_of_year() as i32 - self.weekday() as i32 + 10) / 7;
+
+        if week < 1 {
+            return (self.year - 1, weeks_in_year(self.year - 1));
+        }
+        if week > weeks_in_year(self.year) as i32 {
+            return (self.year + 1, 1);
+        }
+        (self.year, week as u8)
+    }
+}
+
+/// 53 for years starting on a Thursday, and leap years starting on a Wednesday.
+fn weeks_in_year(year: u16) -> u8 {
+    let jan_first = DateTime { day: 1, month: 1, year, minute: 0, hour: 0, second: 0 }.weekday();
+
+    match (jan_first, is_leap_year(year)) {
+        (4, _) | (3, true) => 53,
+        _ => 52,
+    }
+}
+
+impl Add<Duration> for DateTime {
+    type Output = DateTime;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs).expect("overflow when adding duration to date")
+    }
+}
+
+impl Sub<Duration> for DateTime {
+    type Output = DateTime;
 
-        (self.year as u64 * year)
-            + (self.month as u64 * month)
-            + (self.day as u64 * day)
-            + (self.hour as u64 * hour)
-            + (self.minute as u64 * minute)
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs).expect("overflow when subtracting duration from date")
     }
 }
 
@@ -49,50 +201,80 @@ impl Debug for DateTime {
 #[derive(Debug)]
 pub enum DateTimeError {
     ParseError(&'static str),
+    /// the named part isn't a valid value, like month 13
+    OutOfRange(&'static str),
     ParseIntError(IntErrorKind),
 }
 
+impl Display for DateTimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DateTimeError::ParseError(msg) => f.write_str(msg),
+            DateTimeError::OutOfRange(part) => write!(f, "{part} out of range"),
+            DateTimeError::ParseIntError(IntErrorKind::Empty) => f.write_str("a number is missing"),
+            DateTimeError::ParseIntError(IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => f.write_str("a number is too large"),
+            DateTimeError::ParseIntError(_) => f.write_str("expected a number"),
+        }
+    }
+}
+
 impl From<ParseIntError> for DateTimeError {
     fn from(_value: ParseIntError) -> Self {
         DateTimeError::ParseIntError(_value.kind().to_owned())
     }
 }
 
+impl Ord for DateTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.timestamp().cmp(&other.timestamp())
+    }
+}
+
 impl PartialOrd for DateTime {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let self_unix = self.unix_like();
-        let other_unix = other.unix_like();
-
-        self_unix.partial_cmp(&other_unix)
+        Some(self.cmp(other))
     }
 }
 
+/// `hh:mm` or `hh:mm:ss`, anything after the seconds digits is ignored so the
+/// `Z` of `13:00:55Z` is fine.
+fn parse_time(time: &str) -> Result<(u8, u8, u8), DateTimeError> {
+    let mut segments = time.split(':');
+
+    let hour = segments.next().ok_or(DateTimeError::ParseError("unable to retrieve hour"))?.parse()?;
+    let minute = segments.next().ok_or(DateTimeError::ParseError("unable to retrieve minute"))?.parse()?;
+    let second = match segments.next() {
+        Some(s) => {
+            let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            s[..digits].parse()?
+        }
+        None => 0,
+    };
+
+    Ok((hour, minute, second))
+}
+
+/// `yyyy-mm-dd` followed by `sep` and a time for `parse_time`.
+fn parse_date_time(value: &str, sep: char) -> Result<DateTime, DateTimeError> {
+    let (date, time) = value.split_once(sep).ok_or(DateTimeError::ParseError(
+        "found no date and time separator",
+    ))?;
+    let mut date_segments = date.split('-');
+
+    let year = date_segments.next().ok_or(DateTimeError::ParseError("unable to retrieve year"))?.parse()?;
+    let month = date_segments.next().ok_or(DateTimeError::ParseError("unable to retrieve month"))?.parse()?;
+    let day = date_segments.next().ok_or(DateTimeError::ParseError("unable to retrieve day"))?.parse()?;
+    let (hour, minute, second) = parse_time(time)?;
+
+    DateTime::new(year, month, day, hour, minute, second)
+}
+
+/// `2019-01-10 10:00`, as in `endTime` of the account data export.
 impl FromStr for DateTime {
     type Err = DateTimeError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let sep = value.find(' ').ok_or(DateTimeError::ParseError(
-            "found no date and time separator",
-        ))?;
-        let (date, time) = value.split_at(sep);
-        let mut x = date.split('-');
-
-        Ok(DateTime {
-            year: x
-                .next()
-                .ok_or(DateTimeError::ParseError("unable to retrieve year"))?
-                .parse()?,
-            month: x
-                .next()
-                .ok_or(DateTimeError::ParseError("unable to retrieve month"))?
-                .parse()?,
-            day: x
-                .next()
-                .ok_or(DateTimeError::ParseError("unable to retrieve day"))?
-                .parse()?,
-            hour: time[1..3].parse()?,
-            minute: time[4..6].replace(':', "").parse()?,
-        })
+        parse_date_time(value, ' ')
     }
 }
 
@@ -100,6 +282,37 @@ impl FromStr for DateTime {
 pub struct DebugInfo {
     pub file_path: OsString,
     pub line: usize,
+    pub column: usize,
+    pub key: Option<String>,
+    pub value: Option<String>,
+}
+
+impl DebugInfo {
+    pub fn at(file_path: &OsString, pos: Position) -> Self {
+        DebugInfo { file_path: file_path.clone(), line: pos.line, column: pos.column, key: None, value: None }
+    }
+
+    pub fn with_key(mut self, key: &str) -> Self {
+        self.key = Some(key.to_owned());
+        self
+    }
+
+    pub fn with_value(mut self, value: &Value) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+}
+
+impl Display for DebugInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file_path.to_string_lossy(), self.line, self.column)?;
+
+        match (&self.key, &self.value) {
+            (Some(key), Some(value)) => write!(f, " ({key}: {value})"),
+            (Some(key), None) => write!(f, " ({key})"),
+            _ => Ok(()),
+        }
+    }
 }
 
 
@@ -132,181 +345,379 @@ pub struct DebugInfo {
 */
 
 pub trait BuilderTrait {
-    fn append(&mut self, s: &str, d: DebugInfo) -> Result<(), DateTimeError>;
+    /// Called once for every key/value pair of a record, in file order.
+    fn append(&mut self, key: &str, value: Value, d: DebugInfo) -> error::Result<()>;
+    /// Called after the last pair of a record has been appended.
+    fn finish_record(&mut self, d: DebugInfo) -> error::Result<()>;
+    /// Drops whatever was appended for the current record. Used to skip bad
+    /// records in lenient mode.
+    fn discard_record(&mut self);
+    /// Keys that were seen but had nowhere to go, with how often.
+    fn unknown_keys(&self) -> &BTreeMap<String, usize>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FieldKind {
+    Date,
+    String,
+    Number,
+    Bool,
+}
+
+/// Maps the JSON keys of one export format onto the columns of
+/// `BIG_HISTORY_TABLE`, so every format loads into the same table.
+pub struct Schema {
+    pub name: &'static str,
+    /// (json key, column, kind)
+    pub keys: &'static [(&'static str, &'static str, FieldKind)],
+    pub parse_date: fn(&str) -> Result<DateTime, DateTimeError>,
+}
+
+// "endTime": "2023-08-27 22:44", "artistName": ..., "trackName": ..., "msPlayed": 5150
+pub static SMALL_HISTORY_SCHEMA: Schema = Schema {
+    name: "account data",
+    keys: &[
+        ("endTime", "time", FieldKind::Date),
+        ("artistName", "artist", FieldKind::String),
+        ("trackName", "song", FieldKind::String),
+        ("msPlayed", "msplayed", FieldKind::Number),
+        // StreamingHistory_podcast_*.json
+        ("podcastName", "episode_show_name", FieldKind::String),
+        ("episodeName", "episode_name", FieldKind::String),
+    ],
+    parse_date: DateTime::from_str,
+};
+
+pub static BIG_HISTORY_SCHEMA: Schema = Schema {
+    name: "extended streaming history",
+    keys: &[
+        // "ts": "2010-11-02T15:42:08Z",
+        ("ts", "time", FieldKind::Date),
+        ("username", "username", FieldKind::String),
+        ("platform", "platform", FieldKind::String),
+        ("ms_played", "msplayed", FieldKind::Number),
+        ("conn_country", "country", FieldKind::String),
+        ("ip_addr_decrypted", "ip_addr", FieldKind::String),
+        ("user_agent_decrypted", "user_agent", FieldKind::String),
+        ("master_metadata_track_name", "song", FieldKind::String),
+        ("master_metadata_album_artist_name", "artist", FieldKind::String),
+        ("master_metadata_album_album_name", "album", FieldKind::String),
+        ("spotify_track_uri", "track_uri", FieldKind::String),
+        ("episode_name", "episode_name", FieldKind::String),
+        ("episode_show_name", "episode_show_name", FieldKind::String),
+        ("spotify_episode_uri", "episode_uri", FieldKind::String),
+        ("reason_start", "reason_start", FieldKind::String),
+        ("reason_end", "reason_end", FieldKind::String),
+        ("shuffle", "shuffle", FieldKind::Bool),
+        ("skipped", "skipped", FieldKind::Bool),
+        ("offline", "offline", FieldKind::Bool),
+        ("offline_timestamp", "offline_timestamp", FieldKind::Number),
+        ("incognito_mode", "incognito_mode", FieldKind::Bool),
+    ],
+    parse_date: to_timestamp_big_history,
+};
+
+impl Schema {
+    pub fn key_of(&self, key: &str) -> Option<usize> {
+        self.keys.iter().position(|(k, _, _)| *k == key)
+    }
+
+    /// Kind of value `column` holds in tables of this format.
+    pub fn kind_of(&self, column: &str) -> Option<FieldKind> {
+        self.keys.iter().find(|(_, c, _)| *c == column).map(|(_, _, kind)| *kind)
+    }
+
+    /// `value` as a field of `column`, which holds `kind`. A value that isn't
+    /// one is an error rather than a default, so lenient mode can skip the record.
+    fn to_field(&self, column: &str, kind: FieldKind, value: Value) -> error::Result<Field> {
+        if value == Value::Null {
+            return Ok(Field::Null);
+        }
+
+        let wrong_type = |kind: &str, value: &str| Error::new(DataErrors::WrongType(format!("{column} expects {kind}, got '{value}'")));
+
+        Ok(match kind {
+            FieldKind::Date => Field::Date((self.parse_date)(&value.as_text())?),
+            FieldKind::String => Field::String(value.into_text()),
+            FieldKind::Number => {
+                let text = value.as_text();
+                Field::Number(text.parse().map_err(|_| wrong_type("a whole number", &text))?)
+            }
+            FieldKind::Bool => match value.as_text().to_lowercase().as_str() {
+                "true" => Field::Bool(true),
+                "false" => Field::Bool(false),
+                other => return Err(wrong_type("true or false", other)),
+            },
+        })
+    }
+}
+
+/// Collects the pairs of one record by key so the order and completeness of
+/// the export doesn't matter. Columns nothing was appended for end up as `Field::Null`.
+struct RecordBuffer {
+    schema: &'static Schema,
+    /// table column of every schema key, `None` if the table lacks it
+    columns: Vec<Option<usize>>,
+    buf: Vec<Option<(Value, DebugInfo)>>,
+    /// keys not part of the schema, with how often they were seen
+    unknown_keys: BTreeMap<String, usize>,
+}
+
+impl RecordBuffer {
+    fn new(schema: &'static Schema, table: &Table) -> Self {
+        let columns = schema.keys.iter().map(|(_, column, _)| table.get_col(column).ok()).collect();
+
+        RecordBuffer { schema, columns, buf: vec![None; schema.keys.len()], unknown_keys: BTreeMap::new() }
+    }
+
+    fn append(&mut self, key: &str, value: Value, d: DebugInfo) {
+        match self.schema.key_of(key) {
+            Some(idx) if self.columns[idx].is_some() => self.buf[idx] = Some((value, d)),
+            _ => *self.unknown_keys.entry(key.to_owned()).or_insert(0) += 1,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.buf.iter_mut().for_each(|value| *value = None);
+    }
+
+    fn take_row(&mut self, width: usize) -> error::Result<Vec<Field>> {
+        let mut row = vec![Field::Null; width];
+
+        for (idx, value) in self.buf.iter_mut().enumerate() {
+            let (Some((value, d)), Some(col)) = (value.take(), self.columns[idx]) else { continue };
+            let d = d.with_value(&value);
+
+            let (_, column, kind) = self.schema.keys[idx];
+            row[col] = self.schema.to_field(column, kind, value).map_err(|e| e.with_debug(d))?;
+        }
+
+        Ok(row)
+    }
 }
 
 pub struct SmallBuilder<'a> {
-    buf: [String; 4],
-    ptr: usize,
+    record: RecordBuffer,
     pub table: &'a mut Table,
 }
 
 impl<'a> SmallBuilder<'a> {
     pub fn new(tbl: &'a mut Table) -> Self {
-        SmallBuilder { buf: ["".to_owned(),"".to_owned(),"".to_owned(), "".to_owned()], ptr: 0, table: tbl }
+        SmallBuilder { record: RecordBuffer::new(&SMALL_HISTORY_SCHEMA, tbl), table: tbl }
     }
 }
 
 impl BuilderTrait for SmallBuilder<'_> {
-    fn append(&mut self, s: &str, _d: DebugInfo) -> Result<(), DateTimeError> {
-        if self.ptr == self.buf.len() - 1 {
-            self.buf[self.ptr] = s.to_owned();
-            self.ptr = 0;
-
-            //println!("{:?}", self.buf);
-
-            self.table.insert(
-                [ 
-                    Field::Date(self.buf[0].as_str().parse()?),
-                    Field::String(self.buf[1].to_lowercase()),
-                    Field::String(self.buf[2].to_lowercase()),
-                    Field::Number(self.buf[3].parse().unwrap_or_default()),
-                    ]).expect("COULD NOT INSERT");
-        } else {
-            self.buf[self.ptr] = s.to_owned();
-            self.ptr += 1;
-        }
+    fn unknown_keys(&self) -> &BTreeMap<String, usize> {
+        &self.record.unknown_keys
+    }
 
+    fn append(&mut self, key: &str, value: Value, d: DebugInfo) -> error::Result<()> {
+        self.record.append(key, value, d);
         Ok(())
     }
+
+    fn finish_record(&mut self, d: DebugInfo) -> error::Result<()> {
+        let row = self.record.take_row(self.table.header.len())?;
+        self.table.insert(row).map_err(|e| Error::new(e).with_debug(d))
+    }
+
+    fn discard_record(&mut self) {
+        self.record.clear();
+    }
 }
 
 pub struct BigBuilder<'a> {
-    buf: [String; 21],
-    ptr: usize,
+    record: RecordBuffer,
     pub table: &'a mut Table,
 }
 
 impl<'a> BigBuilder<'a> {
     pub fn new(tbl: &'a mut Table) -> Self {
-        let strarr: [String; 21] =  Default::default();
-        BigBuilder { buf: strarr, ptr: 0, table: tbl }
+        BigBuilder { record: RecordBuffer::new(&BIG_HISTORY_SCHEMA, tbl), table: tbl }
     }
 }
 
 // "ts": "2012-02-08T13:00:55Z",
 
 pub fn to_timestamp_big_history(value: &str) -> Result<DateTime, DateTimeError> {
-    let sep = value.find('T').ok_or(DateTimeError::ParseError(
-        "found no date and time separator",
-    ))?;
-
-    let (date, time) = value.split_at(sep);
-    let mut date_segments = date.split('-');
-    let mut time_segments = time.get(1..).unwrap().split(":");
-
-    Ok(DateTime {
-        year: date_segments
-            .next()
-            .ok_or(DateTimeError::ParseError("unable to retrieve year"))?
-            .parse()?,
-        month: date_segments
-            .next()
-            .ok_or(DateTimeError::ParseError("unable to retrieve month"))?
-            .parse()?,
-        day: date_segments
-            .next()
-            .ok_or(DateTimeError::ParseError("unable to retrieve day"))?
-            .parse()?,
-        hour: time_segments
-            .next()
-            .ok_or(DateTimeError::ParseError("unable to retrieve day"))?
-            .parse()?,
-        minute: time_segments
-            .next()
-            .ok_or(DateTimeError::ParseError("unable to retrieve day"))?
-            .parse()?,
-    })
+    parse_date_time(value, 'T')
 }
 
 impl BuilderTrait for BigBuilder<'_> {
-    fn append(&mut self, s: &str, _d: DebugInfo) -> Result<(), DateTimeError> {
-        if self.ptr == self.buf.len() - 1 {
-            self.buf[self.ptr] = s.to_string();
-            self.ptr = 0;
-
-            //println!("{:?}", self.buf);
-
-            self.table.insert(
-                [ 
-                    // "ts": "2010-11-02T15:42:08Z",
-                    // - !!! - Field::Date(self.buf[0].parse()?),
-                    Field::Date(to_timestamp_big_history(&self.buf[0])?),
-                    // - !!! -
-                    // "username": "jonathanhermin",
-                    Field::String(self.buf[1].to_lowercase()),
-                    // "platform": "Windows 7 (Enterprise Ed) SP0 [x86 0]",
-                    Field::String(self.buf[2].to_lowercase()),
-                    // "ms_played": 2890,
-                    Field::Number(self.buf[3].parse().unwrap_or_default()),
-                    // "conn_country": "SE",
-                    Field::String(self.buf[4].to_lowercase()),
-                    // "ip_addr_decrypted": "83.172.84.28",
-                    Field::String(self.buf[5].to_lowercase()),
-                    // "user_agent_decrypted": null,
-                    Field::String(self.buf[6].to_lowercase()),
-                    // "master_metadata_track_name": "The Black Lake",
-                    Field::String(self.buf[7].to_lowercase()),
-                    // "master_metadata_album_artist_name": "Patrick Doyle",
-                    Field::String(self.buf[8].to_lowercase()),
-                    // "master_metadata_album_album_name": "Harry Potter And The Goblet Of Fire (Original Motion Picture Soundtrack)",
-                    Field::String(self.buf[9].to_lowercase()),
-                    // "spotify_track_uri": "spotify:track:38GqVS4rDFnL0291QCiza9",
-                    Field::String(self.buf[10].to_lowercase()),
-                    // "episode_name": null,
-                    Field::String(self.buf[11].to_lowercase()),
-                    // "episode_show_name": null,
-                    Field::String(self.buf[12].to_lowercase()),
-                    // "spotify_episode_uri": null,
-                    Field::String(self.buf[13].to_lowercase()),
-                    // "reason_start": "clickrow",
-                    Field::String(self.buf[14].to_lowercase()),
-                    // "reason_end": "clickrow",
-                    Field::String(self.buf[15].to_lowercase()),
-                    // "shuffle": false, (BOOL !! !!)
-                    Field::Bool(self.buf[16].to_lowercase() == "true"),
-                    // "skipped": true,
-                    Field::Bool(self.buf[17].to_lowercase() == "true"),
-                    // "offline": false,
-                    Field::Bool(self.buf[18].to_lowercase() == "true"),
-                    // "offline_timestamp": 0,
-                    Field::Number(self.buf[19].parse().unwrap_or_default()),
-                    // "incognito_mode": null
-                    Field::String(self.buf[20].to_lowercase()),
-                    ]).expect("COULD NOT INSERT");
-        } else {
-            self.buf[self.ptr] = s.to_string();
-            self.ptr += 1;
-        }
+    fn unknown_keys(&self) -> &BTreeMap<String, usize> {
+        &self.record.unknown_keys
+    }
 
+    fn append(&mut self, key: &str, value: Value, d: DebugInfo) -> error::Result<()> {
+        self.record.append(key, value, d);
         Ok(())
     }
+
+    fn finish_record(&mut self, d: DebugInfo) -> error::Result<()> {
+        let row = self.record.take_row(self.table.header.len())?;
+        self.table.insert(row).map_err(|e| Error::new(e).with_debug(d))
+    }
+
+    fn discard_record(&mut self) {
+        self.record.clear();
+    }
+}
+
+/// What a call to `parse` did. Rejected records are only collected in lenient
+/// mode, otherwise the first bad record fails the whole file.
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    pub records: usize,
+    pub rejected: Vec<Error>,
+    /// keys the builder had nowhere to put, with how often
+    pub unknown_keys: BTreeMap<String, usize>,
+}
+
+pub fn parse(path: PathBuf, builder: &mut dyn BuilderTrait, lenient: bool) -> error::Result<ParseReport> {
+    let file = File::open(&path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let file_path = path.file_name().unwrap_or_default().to_owned();
+
+    parse_reader(BufReader::new(file), file_path, builder, lenient)
+}
+
+/// Like `parse`, for exports that don't live in a file of their own. `file_path`
+/// is only used to point at problems.
+pub fn parse_reader<R: Read>(reader: R, file_path: OsString, builder: &mut dyn BuilderTrait, lenient: bool) -> error::Result<ParseReport> {
+    parse_records(State::new(reader), file_path, builder, lenient, false)
+}
+
+/// Like `parse_reader`, for a run of records `split_array` cut out of an export
+/// at `start`. Problems are pointed at in the whole export.
+pub fn parse_chunk<R: Read>(reader: R, start: Position, file_path: OsString, builder: &mut dyn BuilderTrait, lenient: bool) -> error::Result<ParseReport> {
+    parse_records(State::at(reader, start), file_path, builder, lenient, true)
 }
 
-pub fn parse(path: PathBuf, builder: &mut dyn BuilderTrait) -> io::Result<()> {
+fn parse_records<R: Read>(mut state: State<R>, file_path: OsString, builder: &mut dyn BuilderTrait, lenient: bool, chunk: bool) -> error::Result<ParseReport> {
+    let mut report = ParseReport::default();
 
-    let file = File::open(&path)?;
-    let reader = BufReader::new(file);
+    let mut record = |state: &mut State<R>| -> error::Result<()> {
+        let start = DebugInfo::at(&file_path, state.position());
+        let mut rejected: Option<Error> = None;
 
-    let mut i = 0;
-    for line in reader.lines() {
-        i += 1;
-        let l = line?.trim().to_owned();
-        if !l.contains(":") { continue; }
+        state.walk_object(|state, key| -> error::Result<()> {
+            let debug = DebugInfo::at(&file_path, state.position()).with_key(&key);
+            let value = state.parse_value()?;
 
-        let debug = DebugInfo {
-            line: i,
-            file_path: path.file_name().unwrap_or_default().to_owned(),
+            // once a record is rejected the rest of it is only read past
+            if rejected.is_none() {
+                rejected = builder.append(&key, value, debug).err();
+            }
+            Ok(())
+        })?;
+
+        let result = match rejected {
+            Some(e) => Err(e),
+            None => builder.finish_record(start),
         };
 
-        let sanitized = &l
-            .split_at(l.find(':').unwrap_or_default() + 2)
-            .1
-            .replace('"', "")
-            .replace(',', "");
+        match result {
+            Ok(()) => report.records += 1,
+            Err(e) if lenient => {
+                builder.discard_record();
+                report.rejected.push(e);
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    };
+
+    let walked = (|| -> error::Result<()> {
+        if chunk {
+            return state.walk_items(&mut record);
+        }
+
+        state.skip_bom()?;
+        state.skip_whitespace()?;
+
+        // exports are an array of records, but a lone record is accepted as well
+        match state.peek()? {
+            Some(b'{') => record(&mut state)?,
+            _ => state.walk_array(&mut record)?,
+        }
+
+        state.expect_end()?;
+        Ok(())
+    })();
 
-        builder.append(sanitized, debug).expect("ERR");
+    walked.map_err(|mut e| {
+        if let (None, ErrorKind::Json(json)) = (&e.debug, &e.kind) {
+            e.debug = json.position().map(|pos| Box::new(DebugInfo::at(&file_path, pos)));
+        }
+        e
+    })?;
+
+    report.unknown_keys = builder.unknown_keys().clone();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::table::BIG_HISTORY_TABLE;
+
+    const EXPORT: &str = r#"[
+  {"ts": "2023-08-27T22:44:00Z", "ms_played": 4991, "master_metadata_track_name": "Got To Be There"},
+  {"ts": "2023-08-27T22:50:00Z", "ms_played": "a lot", "master_metadata_track_name": "Ben"},
+  {"ts": "2023-13-01T00:00:00Z", "ms_played": 1000, "master_metadata_track_name": "Thriller"},
+  {"ts": "2023-08-28T10:00:00Z", "ms_played": 2000, "master_metadata_track_name": "Bad", "shuffle": "yes"},
+  {"ts": "2023-08-28T11:00:00Z", "ms_played": 3000, "master_metadata_track_name": "Beat It", "offline": null}
+]"#;
+
+    fn load(lenient: bool) -> (Table, error::Result<ParseReport>) {
+        let mut tbl = Table::new(BIG_HISTORY_TABLE);
+        let report = parse_reader(EXPORT.as_bytes(), "endsong_0.json".into(), &mut BigBuilder::new(&mut tbl), lenient);
+        (tbl, report)
+    }
+
+    #[test]
+    fn lenient_collects_rejects() {
+        let (tbl, report) = load(true);
+        let report = report.unwrap();
+
+        assert_eq!(report.records, 2);
+        let rejected: Vec<String> = report.rejected.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            rejected,
+            [
+                "endsong_0.json:3:47 (ms_played: \"a lot\"): msplayed expects a whole number, got 'a lot'",
+                "endsong_0.json:4:10 (ts: \"2023-13-01T00:00:00Z\"): invalid date: month out of range",
+                "endsong_0.json:5:101 (shuffle: \"yes\"): shuffle expects true or false, got 'yes'",
+            ]
+        );
+
+        // the rejects leave nothing behind
+        let song = tbl.get_col("song").unwrap();
+        let songs: Vec<Field> = (0..tbl.len()).map(|row| tbl.get(row, song)).collect();
+        assert_eq!(songs, [Field::String("Got To Be There".into()), Field::String("Beat It".into())]);
     }
 
-    Ok(())
+    #[test]
+    fn strict_fails_on_the_first_reject() {
+        let (tbl, report) = load(false);
+        let e = report.unwrap_err();
+
+        assert!(matches!(e.kind, ErrorKind::Data(DataErrors::WrongType(_))));
+        assert_eq!(e.to_string(), "endsong_0.json:3:47 (ms_played: \"a lot\"): msplayed expects a whole number, got 'a lot'");
+        assert_eq!(tbl.len(), 1);
+    }
+
+    #[test]
+    fn date_errors_read_as_text() {
+        let messages = ["2023-08-27 22:44:00", "2023-08-27T22:44", "2023-08-xxT22:44:00Z", "2023-08-27T99999:00:00Z", "2023--27T22:44:00Z", "2023-02-29T22:44:00Z"]
+            .map(|s| to_timestamp_big_history(s).map_or_else(|e| e.to_string(), |_| "ok".to_owned()));
+
+        assert_eq!(messages[0], "found no date and time separator");
+        assert_eq!(messages[1], "ok");
+        assert_eq!(messages[2], "expected a number");
+        assert_eq!(messages[3], "a number is too large");
+        assert_eq!(messages[4], "a number is missing");
+        assert_eq!(messages[5], "day out of range");
+    }
 }
@@ -1,66 +1,826 @@
-pub struct State {
-    cursor: usize,
-    buf: Vec<char>
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Read},
+    ops::Range,
+};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Streaming cursor over a byte source. Only one chunk of the input is held in
+/// memory at a time, so arbitrarily large exports can be walked.
+pub struct State<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    head: usize,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
-impl State {
-    pub fn new(from: &str) -> Self {
+impl<R: Read> State<R> {
+    pub fn new(reader: R) -> Self {
         State {
-            cursor: 0,
-            buf: from.chars().collect()
+            reader,
+            buf: Vec::new(),
+            head: 0,
+            offset: 0,
+            line: 1,
+            column: 1,
         }
     }
 
+    /// Like `new`, for input cut out of a bigger one at `start`, so positions
+    /// point into the bigger one.
+    pub fn at(reader: R, start: Position) -> Self {
+        State { offset: start.offset, line: start.line, column: start.column, ..State::new(reader) }
+    }
+
     pub fn cursor(&self) -> usize {
-        self.cursor
+        self.offset
     }
 
-    pub fn peek(&self) -> Option<&char> {
-        self.buf.get(self.cursor)
+    pub fn position(&self) -> Position {
+        Position { offset: self.offset, line: self.line, column: self.column }
     }
 
-    pub fn pop(&mut self) -> Option<&char> {
-        match self.buf.get(self.cursor) {
-            Some(val) => {
-                self.cursor += 1;
-                Some(&val)
-            },
-            None => None
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.head < self.buf.len() {
+            return Ok(true);
         }
+
+        self.buf.resize(CHUNK_SIZE, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buf) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        self.buf.truncate(read);
+        self.head = 0;
+
+        Ok(read != 0)
     }
-}
 
-pub enum Error {
-    UnexpendedEnd(usize)
+    pub fn peek(&mut self) -> Result<Option<u8>, Error> {
+        if !self.fill()? {
+            return Ok(None);
+        }
+
+        Ok(Some(self.buf[self.head]))
+    }
+
+    pub fn pop(&mut self) -> Result<Option<u8>, Error> {
+        let val = match self.peek()? {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+
+        self.head += 1;
+        self.offset += 1;
+
+        if val == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if val & 0xC0 != 0x80 {
+            // continuation bytes belong to the previous character
+            self.column += 1;
+        }
+
+        Ok(Some(val))
+    }
+
+    fn expect_pop(&mut self) -> Result<u8, Error> {
+        let pos = self.position();
+        self.pop()?.ok_or(Error::UnexpectedEnd(pos))
+    }
+
+    pub fn skip_whitespace(&mut self) -> Result<(), Error> {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek()? {
+            self.pop()?;
+        }
+
+        Ok(())
+    }
+
+    /// Skips the UTF-8 byte order mark some editors put at the start of files.
+    pub fn skip_bom(&mut self) -> Result<(), Error> {
+        if self.offset != 0 || !self.fill()? {
+            return Ok(());
+        }
+
+        if self.buf[self.head..].starts_with(&[0xEF, 0xBB, 0xBF]) {
+            self.head += 3;
+            self.offset += 3;
+        }
+
+        Ok(())
+    }
+
+    /// Consumes `ch` (after any whitespace) or fails with the character found instead.
+    pub fn expect(&mut self, ch: u8) -> Result<(), Error> {
+        self.skip_whitespace()?;
+        let pos = self.position();
+
+        match self.pop()? {
+            Some(val) if val == ch => Ok(()),
+            Some(val) => Err(Error::UnexpectedChar(val as char, pos)),
+            None => Err(Error::UnexpectedEnd(pos)),
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str) -> Result<(), Error> {
+        for ch in literal.bytes() {
+            let pos = self.position();
+            match self.pop()? {
+                Some(val) if val == ch => {}
+                Some(val) => return Err(Error::UnexpectedChar(val as char, pos)),
+                None => return Err(Error::UnexpectedEnd(pos)),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn parse_string(&mut self) -> Result<String, Error> {
+        self.expect(b'"')?;
+        let start = self.position();
+
+        let mut b: Vec<u8> = Vec::new();
+
+        loop {
+            let pos = self.position();
+            match self.expect_pop()? {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.expect_pop()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape(pos)?,
+                        val => return Err(Error::InvalidEscape(val as char, pos)),
+                    };
+
+                    let mut utf8 = [0; 4];
+                    b.extend_from_slice(escaped.encode_utf8(&mut utf8).as_bytes());
+                }
+                val if val < 0x20 => return Err(Error::UnexpectedChar(val as char, pos)),
+                val => b.push(val),
+            }
+        }
+
+        String::from_utf8(b).map_err(|_| Error::InvalidUtf8(start))
+    }
+
+    fn parse_hex4(&mut self, pos: Position) -> Result<u32, Error> {
+        let mut n = 0;
+
+        for _ in 0..4 {
+            let val = self.expect_pop()?;
+            let digit = (val as char).to_digit(16).ok_or(Error::InvalidUnicode(pos))?;
+            n = n * 16 + digit;
+        }
+
+        Ok(n)
+    }
+
+    fn parse_unicode_escape(&mut self, pos: Position) -> Result<char, Error> {
+        let high = self.parse_hex4(pos)?;
+
+        let code = match high {
+            0xD800..=0xDBFF => {
+                // surrogate pair, the low half has to follow as another \u escape
+                self.expect_literal("\\u")
+                    .map_err(|_| Error::InvalidUnicode(pos))?;
+                let low = self.parse_hex4(pos)?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(Error::InvalidUnicode(pos));
+                }
+
+                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+            }
+            0xDC00..=0xDFFF => return Err(Error::InvalidUnicode(pos)),
+            code => code,
+        };
+
+        char::from_u32(code).ok_or(Error::InvalidUnicode(pos))
+    }
+
+    fn parse_number(&mut self) -> Result<String, Error> {
+        let start = self.position();
+        let mut b = String::new();
+
+        if let Some(b'-') = self.peek()? {
+            b.push('-');
+            self.pop()?;
+        }
+
+        let digits = |state: &mut Self, b: &mut String| -> Result<usize, Error> {
+            let mut count = 0;
+            while let Some(val @ b'0'..=b'9') = state.peek()? {
+                b.push(val as char);
+                state.pop()?;
+                count += 1;
+            }
+            Ok(count)
+        };
+
+        match digits(self, &mut b)? {
+            0 => return Err(Error::InvalidNumber(start)),
+            n if n > 1 && b.trim_start_matches('-').starts_with('0') => {
+                return Err(Error::InvalidNumber(start))
+            }
+            _ => {}
+        }
+
+        if let Some(b'.') = self.peek()? {
+            b.push('.');
+            self.pop()?;
+            if digits(self, &mut b)? == 0 {
+                return Err(Error::InvalidNumber(start));
+            }
+        }
+
+        if let Some(val @ (b'e' | b'E')) = self.peek()? {
+            b.push(val as char);
+            self.pop()?;
+            if let Some(sign @ (b'+' | b'-')) = self.peek()? {
+                b.push(sign as char);
+                self.pop()?;
+            }
+            if digits(self, &mut b)? == 0 {
+                return Err(Error::InvalidNumber(start));
+            }
+        }
+
+        Ok(b)
+    }
+
+    /// Parses one complete value, including any nested arrays and objects.
+    pub fn parse_value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace()?;
+        let pos = self.position();
+
+        match self.peek()? {
+            Some(b'"') => Ok(Value::String(self.parse_string()?)),
+            Some(b'n') => self.expect_literal("null").map(|_| Value::Null),
+            Some(b't') => self.expect_literal("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect_literal("false").map(|_| Value::Bool(false)),
+            Some(b'-' | b'0'..=b'9') => Ok(Value::Number(self.parse_number()?)),
+            Some(b'[') => {
+                let mut items = Vec::new();
+                self.walk_array(|state| -> Result<(), Error> {
+                    items.push(state.parse_value()?);
+                    Ok(())
+                })?;
+                Ok(Value::Array(items))
+            }
+            Some(b'{') => {
+                let mut entries = Vec::new();
+                self.walk_object(|state, key| -> Result<(), Error> {
+                    entries.push((key, state.parse_value()?));
+                    Ok(())
+                })?;
+                Ok(Value::Object(entries))
+            }
+            Some(val) => Err(Error::UnexpectedChar(val as char, pos)),
+            None => Err(Error::UnexpectedEnd(pos)),
+        }
+    }
+
+    /// Walks an array, handing the state to `item` once per element. `item` must
+    /// consume exactly one value.
+    pub fn walk_array<E, F>(&mut self, mut item: F) -> Result<(), E>
+    where
+        E: From<Error>,
+        F: FnMut(&mut Self) -> Result<(), E>,
+    {
+        self.expect(b'[')?;
+        self.skip_whitespace()?;
+
+        if let Some(b']') = self.peek()? {
+            self.pop()?;
+            return Ok(());
+        }
+
+        loop {
+            item(self)?;
+            self.skip_whitespace()?;
+
+            let pos = self.position();
+            match self.pop()? {
+                Some(b',') => continue,
+                Some(b']') => return Ok(()),
+                Some(val) => return Err(Error::UnexpectedChar(val as char, pos).into()),
+                None => return Err(Error::UnexpectedEnd(pos).into()),
+            }
+        }
+    }
+
+    /// Walks comma separated values up to the end of the input, as `split_array`
+    /// cuts them out of an array. `item` must consume exactly one value.
+    pub fn walk_items<E, F>(&mut self, mut item: F) -> Result<(), E>
+    where
+        E: From<Error>,
+        F: FnMut(&mut Self) -> Result<(), E>,
+    {
+        loop {
+            item(self)?;
+            self.skip_whitespace()?;
+
+            let pos = self.position();
+            match self.pop()? {
+                Some(b',') => continue,
+                Some(val) => return Err(Error::UnexpectedChar(val as char, pos).into()),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Walks an object, handing the state and key to `entry` once per member.
+    /// `entry` must consume exactly one value.
+    pub fn walk_object<E, F>(&mut self, mut entry: F) -> Result<(), E>
+    where
+        E: From<Error>,
+        F: FnMut(&mut Self, String) -> Result<(), E>,
+    {
+        self.expect(b'{')?;
+        self.skip_whitespace()?;
+
+        if let Some(b'}') = self.peek()? {
+            self.pop()?;
+            return Ok(());
+        }
+
+        loop {
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            self.skip_whitespace()?;
+            entry(self, key)?;
+            self.skip_whitespace()?;
+
+            let pos = self.position();
+            match self.pop()? {
+                Some(b',') => self.skip_whitespace()?,
+                Some(b'}') => return Ok(()),
+                Some(val) => return Err(Error::UnexpectedChar(val as char, pos).into()),
+                None => return Err(Error::UnexpectedEnd(pos).into()),
+            }
+        }
+    }
+
+    /// Fails unless only whitespace remains.
+    pub fn expect_end(&mut self) -> Result<(), Error> {
+        self.skip_whitespace()?;
+        let pos = self.position();
+
+        match self.peek()? {
+            Some(val) => Err(Error::UnexpectedChar(val as char, pos)),
+            None => Ok(()),
+        }
+    }
 }
 
-pub fn is_string(s: &str) -> Result<String, Error> {
-    let mut state = State::new(s);
-    state.pop();
+/// A run of items `split_array` found: where its bytes are and the position they start at.
+pub type Run = (Range<usize>, Position);
+
+/// Cuts the array `reader` holds into runs of whole items, each at least
+/// `size` bytes but the last, along with the position they start at. A run
+/// goes from right after the `[` or `,` before its first item to right before
+/// the `,` or `]` after its last, for `State::walk_items`. The input is read
+/// a chunk at a time and only brackets and strings are looked at, so `None`
+/// means it's no array, or one with an empty item, and anything else wrong is
+/// left to whatever parses the runs.
+pub fn split_array<R: Read>(mut reader: R, size: usize) -> io::Result<Option<Vec<Run>>> {
+    const BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
 
-    let mut b: Vec<&char> = Vec::new();
+    let mut pos = Position { offset: 0, line: 1, column: 1 };
+    let mut runs = Vec::new();
+    let mut start = None;
+    let mut depth = 0usize;
+    let mut closed = false;
+    let mut in_string = false;
+    let mut escaped = false;
+    // whether there's more than whitespace since the last `[` or `,`
+    let mut in_item = false;
+    let mut bom = 0;
 
+    let mut buf = vec![0; CHUNK_SIZE];
     loop {
-        match state.peek() {
-            Some('"') => {
-                if state.cursor != 0 {
-                    state.pop();
-                    break;
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for &b in &buf[..read] {
+            let at = pos;
+            pos.offset += 1;
+
+            // like `State::skip_bom`, the mark takes up no column
+            if at.offset == bom && bom < BOM.len() && b == BOM[bom] {
+                bom += 1;
+                continue;
+            }
+            if b == b'\n' {
+                pos.line += 1;
+                pos.column = 1;
+            } else if b & 0xC0 != 0x80 {
+                pos.column += 1;
+            }
+
+            if in_string {
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
                 }
+                continue;
+            }
 
-                state.pop();
-            },
-            Some(val) => {
-                b.push(val);
-                state.pop();
-                return Ok("hello".to_owned())
-            },
-            None => return Err(Error::UnexpendedEnd(state.cursor()))
-        };
+            match b {
+                b' ' | b'\t' | b'\r' | b'\n' => continue,
+                _ if closed => return Ok(None),
+                b'[' if depth == 0 => {
+                    start = Some(pos);
+                    depth = 1;
+                    continue;
+                }
+                _ if depth == 0 => return Ok(None),
+                b'"' => in_string = true,
+                b'[' | b'{' => depth += 1,
+                b']' | b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let Some(run) = start.filter(|_| in_item) else { return Ok(None) };
+                        runs.push((run.offset..at.offset, run));
+                        closed = true;
+                        continue;
+                    }
+                }
+                b',' if depth == 1 => {
+                    let Some(run) = start.filter(|_| in_item) else { return Ok(None) };
+                    if at.offset - run.offset >= size {
+                        runs.push((run.offset..at.offset, run));
+                        start = Some(pos);
+                    }
+                    in_item = false;
+                    continue;
+                }
+                _ => {}
+            }
+            in_item = true;
+        }
+    }
+
+    // half a mark isn't one, parsing it whole says what's wrong
+    let bom_ok = bom == 0 || bom == BOM.len();
+    Ok((closed && bom_ok).then_some(runs))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// kept as the source text so no precision is lost before a builder decides the type
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Strings come back unquoted, everything else as compact JSON.
+    pub fn as_text(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    /// `as_text` without copying strings.
+    pub fn into_text(self) -> String {
+        match self {
+            Value::String(s) => s,
+            other => other.to_string(),
+        }
+    }
+}
+
+fn write_json_string(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => f.write_str(n),
+            Value::String(s) => write_json_string(f, s),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(entries) => {
+                f.write_str("{")?;
+                for (i, (key, val)) in entries.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", val)?;
+                }
+                f.write_str("}")
+            }
+        }
     }
-    
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnexpectedEnd(Position),
+    UnexpectedChar(char, Position),
+    InvalidEscape(char, Position),
+    InvalidUnicode(Position),
+    InvalidUtf8(Position),
+    InvalidNumber(Position),
+    Io(io::Error),
+}
+
+impl Error {
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Error::UnexpectedEnd(pos)
+            | Error::UnexpectedChar(_, pos)
+            | Error::InvalidEscape(_, pos)
+            | Error::InvalidUnicode(pos)
+            | Error::InvalidUtf8(pos)
+            | Error::InvalidNumber(pos) => Some(*pos),
+            Error::Io(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
 
-    Ok("HELLO".to_owned())
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnexpectedEnd(pos) => write!(f, "unexpected end of input at {}", pos),
+            Error::UnexpectedChar(ch, pos) => write!(f, "unexpected character {:?} at {}", ch, pos),
+            Error::InvalidEscape(ch, pos) => write!(f, "invalid escape '\\{}' at {}", ch, pos),
+            Error::InvalidUnicode(pos) => write!(f, "invalid unicode escape at {}", pos),
+            Error::InvalidUtf8(pos) => write!(f, "string starting at {} is not valid utf-8", pos),
+            Error::InvalidNumber(pos) => write!(f, "invalid number at {}", pos),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
 
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Io(e) => e,
+            other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(offset: usize, line: usize, column: usize) -> Position {
+        Position { offset, line, column }
+    }
+
+    /// The items of every run `split_array` cut `input` into, parsed the way
+    /// chunks are.
+    fn split_items(input: &[u8], size: usize) -> Vec<Value> {
+        let runs = split_array(input, size).unwrap().unwrap();
+        let mut items = Vec::new();
+
+        for (range, start) in runs {
+            let mut state = State::at(&input[range.clone()], start);
+            state
+                .walk_items(|state| -> Result<(), Error> {
+                    items.push(state.parse_value()?);
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(state.cursor(), range.end);
+        }
+        items
+    }
+
+    fn whole_items(input: &[u8]) -> Vec<Value> {
+        let mut state = State::new(input);
+        state.skip_bom().unwrap();
+        match state.parse_value().unwrap() {
+            Value::Array(items) => items,
+            other => panic!("not an array: {}", other),
+        }
+    }
+
+    fn string(json: &str) -> Result<String, Error> {
+        State::new(json.as_bytes()).parse_string()
+    }
+
+    fn value(json: &[u8]) -> Result<Value, Error> {
+        let mut state = State::new(json);
+        let value = state.parse_value()?;
+        state.expect_end()?;
+        Ok(value)
+    }
+
+    fn number(json: &str) -> Result<String, Error> {
+        match value(json.as_bytes())? {
+            Value::Number(n) => Ok(n),
+            other => panic!("not a number: {}", other),
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(string(r#""\"\\\/\b\f\n\r\t""#).unwrap(), "\"\\/\u{8}\u{c}\n\r\t");
+        assert_eq!(string(r#""caf\u00e9 \u00C9""#).unwrap(), "café É");
+        assert_eq!(string("\"Björk 🎵\"").unwrap(), "Björk 🎵");
+        assert_eq!(string(r#""""#).unwrap(), "");
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(string(r#""\ud83c\udfb5""#).unwrap(), "🎵");
+        assert_eq!(string(r#""a\uD83D\uDE00b""#).unwrap(), "a😀b");
+    }
+
+    #[test]
+    fn lone_and_reversed_surrogates() {
+        for bad in [r#""\ud83c""#, r#""\ud83cx""#, r#""\udfb5""#, r#""\udfb5\ud83c""#, r#""\ud83cA""#, r#""\ud83c\n""#] {
+            assert!(matches!(string(bad), Err(Error::InvalidUnicode(pos)) if pos.column == 2), "{bad}");
+        }
+        assert!(matches!(string(r#""\u12g4""#), Err(Error::InvalidUnicode(_))));
+    }
+
+    #[test]
+    fn bad_strings() {
+        assert!(matches!(string(r#""ab\x""#), Err(Error::InvalidEscape('x', pos)) if pos.column == 4));
+        assert!(matches!(string("\"a\nb\""), Err(Error::UnexpectedChar('\n', _))));
+        assert!(matches!(string("\"abc"), Err(Error::UnexpectedEnd(_))));
+        assert!(matches!(string("abc\""), Err(Error::UnexpectedChar('a', _))));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        for bad in [&b"\"\xff\""[..], b"\"a\xc3\"", b"\"\xed\xa0\x80\""] {
+            let result = State::new(bad).parse_string();
+            assert!(matches!(result, Err(Error::InvalidUtf8(pos)) if pos.column == 2), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        for ok in ["0", "-0", "7", "-12", "0.5", "-0.25", "10.010", "1e5", "1E+5", "2.5e-3", "0e0"] {
+            assert_eq!(number(ok).unwrap(), ok);
+        }
+        assert_eq!(number(" 9007199254740993 ").unwrap(), "9007199254740993");
+    }
+
+    #[test]
+    fn bad_numbers() {
+        for bad in ["01", "-01", "00", "-", "1.", "1.e3", "1e", "1e+", "-a"] {
+            assert!(matches!(value(bad.as_bytes()), Err(Error::InvalidNumber(pos)) if pos.column == 1), "{bad}");
+        }
+        assert!(matches!(value(b".5"), Err(Error::UnexpectedChar('.', _))));
+        assert!(matches!(value(b"+1"), Err(Error::UnexpectedChar('+', _))));
+        assert!(matches!(value(b"0x10"), Err(Error::UnexpectedChar('x', _))));
+    }
+
+    #[test]
+    fn minified_and_pretty_agree() {
+        let minified = br#"[{"ts":"2023-01-01T10:00:00Z","master_metadata_track_name":"Hello, Goodbye","master_metadata_album_album_name":"Live: 1999","ms_played":1234,"shuffle":false,"skipped":null,"tags":[1,-0.5,"a,b:c"]}]"#;
+        let pretty = br#"[
+  {
+    "ts" : "2023-01-01T10:00:00Z",
+    "master_metadata_track_name": "Hello, Goodbye",
+    "master_metadata_album_album_name":	"Live: 1999",
+    "ms_played": 1234,
+    "shuffle": false,
+    "skipped": null,
+    "tags": [ 1, -0.5, "a,b:c" ]
+  }
+]
+"#;
+
+        let parsed = value(minified).unwrap();
+        assert_eq!(parsed, value(pretty).unwrap());
+
+        let Value::Array(records) = parsed else { panic!("not an array") };
+        let Value::Object(record) = &records[0] else { panic!("not an object") };
+        assert_eq!(record[1], ("master_metadata_track_name".to_owned(), Value::String("Hello, Goodbye".to_owned())));
+        assert_eq!(record[2].1, Value::String("Live: 1999".to_owned()));
+        assert_eq!(record[3].1, Value::Number("1234".to_owned()));
+        assert_eq!(record[4].1, Value::Bool(false));
+        assert_eq!(record[5].1, Value::Null);
+        assert_eq!(record.len(), 7);
+
+        // and cutting them into runs doesn't split those strings either
+        assert_eq!(split_items(pretty, 1), whole_items(pretty));
+    }
+
+    #[test]
+    fn positions_count_lines_and_characters() {
+        let mut state = State::new("{\n  \"é\": tru }".as_bytes());
+        let err = state.parse_value().unwrap_err();
+        assert!(matches!(err, Error::UnexpectedChar(' ', pos) if pos.line == 2 && pos.column == 11), "{err}");
+    }
+
+    #[test]
+    fn runs_and_their_positions() {
+        let input = b"[1,22,\n333]";
+
+        let runs = split_array(&input[..], 1).unwrap().unwrap();
+        assert_eq!(runs, vec![(1..2, at(1, 1, 2)), (3..5, at(3, 1, 4)), (6..10, at(6, 1, 7))]);
+
+        let runs = split_array(&input[..], 100).unwrap().unwrap();
+        assert_eq!(runs, vec![(1..10, at(1, 1, 2))]);
+    }
+
+    #[test]
+    fn byte_order_mark_takes_no_column() {
+        let input = b"\xEF\xBB\xBF [1, 2]";
+
+        let runs = split_array(&input[..], 1).unwrap().unwrap();
+        assert_eq!(runs[0], (5..6, at(5, 1, 3)));
+        assert_eq!(split_items(input, 1), whole_items(input));
+    }
+
+    #[test]
+    fn strings_can_hold_brackets_and_commas() {
+        let input = br#"[{"a": "x,]}\"[,"}, ["\\", "{"], "]", {"b": [1, {"c": null}]}]"#;
+
+        assert_eq!(split_array(&input[..], 1).unwrap().unwrap().len(), 4);
+        assert_eq!(split_items(input, 1), whole_items(input));
+    }
+
+    #[test]
+    fn items_across_read_chunks() {
+        let input: String = (0..CHUNK_SIZE / 8).map(|i| format!("{}{{\"n\": {i}, \"s\": \"a,]\\\"b\"}}", if i == 0 { "[" } else { ", " })).collect::<String>() + "]";
+
+        for size in [1000, CHUNK_SIZE, 10 * CHUNK_SIZE] {
+            assert_eq!(split_items(input.as_bytes(), size), whole_items(input.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn rejects_what_it_cant_split() {
+        let inputs: [&[u8]; 13] = [b"", b"  ", b"[]", b"[ ]", b"[1,]", b"[,1]", b"[1,,2]", b"[1", b"[1] x", b"[1][2]", b"{\"a\": [1]}", b"1", b"\xEF\xBB[1]"];
+
+        for input in inputs {
+            assert_eq!(split_array(input, 1).unwrap(), None, "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use super::{
+    column::{Column, Values},
+    intern::Symbol,
+    regex::Regex,
+    table::{DataErrors, Field, Table},
+};
+
+/// A boolean expression over the columns of a row, evaluated by `Table::filter`.
+///
+/// Comparisons never match a null field, with the exception of `Eq(.., Field::Null)`
+/// which matches exactly the nulls. Logic is two-valued, so `Not` of a comparison
+/// does match nulls.
+///
+/// Comparisons are exact, the text matchers from `Contains` to `Matches` ignore
+/// case and only ever match string fields.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    Eq(String, Field),
+    NotEq(String, Field),
+    Lt(String, Field),
+    LtEq(String, Field),
+    Gt(String, Field),
+    GtEq(String, Field),
+    /// `lower < value <= upper`
+    Range { column: String, lower: Field, upper: Field },
+    Contains(String, String),
+    StartsWith(String, String),
+    EndsWith(String, String),
+    /// SQL `LIKE` pattern, `%` being any run of chars and `_` any one char,
+    /// `\` escapes either
+    ILike(String, String),
+    /// regular expression found anywhere in the field, see `Regex`
+    Matches(String, String),
+    In(String, Vec<Field>),
+    IsNull(String),
+    IsNotNull(String),
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn and(self, other: Predicate) -> Predicate {
+        match self {
+            Predicate::And(mut all) => {
+                all.push(other);
+                Predicate::And(all)
+            }
+            this => Predicate::And(vec![this, other]),
+        }
+    }
+
+    pub fn or(self, other: Predicate) -> Predicate {
+        match self {
+            Predicate::Or(mut any) => {
+                any.push(other);
+                Predicate::Or(any)
+            }
+            this => Predicate::Or(vec![this, other]),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Predicate {
+        Predicate::Not(Box::new(self))
+    }
+
+    /// Resolves column names once so evaluating a row is only index lookups.
+    pub(crate) fn bind(&self, table: &Table) -> Result<Bound, DataErrors> {
+        let col = |name: &str| table.get_col(name);
+
+        Ok(match self {
+            Predicate::Eq(c, v) => Bound::Eq(col(c)?, v.clone()),
+            Predicate::NotEq(c, v) => Bound::NotEq(col(c)?, v.clone()),
+            Predicate::Lt(c, v) => Bound::Lt(col(c)?, v.clone()),
+            Predicate::LtEq(c, v) => Bound::LtEq(col(c)?, v.clone()),
+            Predicate::Gt(c, v) => Bound::Gt(col(c)?, v.clone()),
+            Predicate::GtEq(c, v) => Bound::GtEq(col(c)?, v.clone()),
+            Predicate::Range { column, lower, upper } => Bound::Range(col(column)?, lower.clone(), upper.clone()),
+            Predicate::Contains(c, s) => Bound::Contains(col(c)?, fold(s).into_owned()),
+            Predicate::StartsWith(c, s) => Bound::StartsWith(col(c)?, fold(s).into_owned()),
+            Predicate::EndsWith(c, s) => Bound::EndsWith(col(c)?, fold(s).into_owned()),
+            Predicate::ILike(c, pattern) => Bound::ILike(col(c)?, like_pattern(&fold(pattern))),
+            Predicate::Matches(c, pattern) => Bound::Matches(
+                col(c)?,
+                Regex::new(pattern, true).map_err(|e| DataErrors::InvalidPattern(format!("invalid pattern '{pattern}': {e}")))?,
+            ),
+            Predicate::In(c, vs) => Bound::In(col(c)?, vs.clone()),
+            Predicate::IsNull(c) => Bound::IsNull(col(c)?),
+            Predicate::IsNotNull(c) => Bound::Not(Box::new(Bound::IsNull(col(c)?))),
+            Predicate::And(ps) => Bound::And(ps.iter().map(|p| p.bind(table)).collect::<Result<_, _>>()?),
+            Predicate::Or(ps) => Bound::Or(ps.iter().map(|p| p.bind(table)).collect::<Result<_, _>>()?),
+            Predicate::Not(p) => Bound::Not(Box::new(p.bind(table)?)),
+        })
+    }
+}
+
+/// The predicate in query syntax.
+impl Display for Predicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let value = |v: &Field| match v {
+            Field::String(s) => format!("'{}'", s.replace('\'', "''")),
+            Field::Date(d) => format!("'{}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2}'", d.year, d.month, d.day, d.hour, d.minute, d.second),
+            other => other.to_string(),
+        };
+        let join = |ps: &[Predicate], op: &str| ps.iter().map(|p| format!("({p})")).collect::<Vec<_>>().join(op);
+
+        match self {
+            Predicate::Eq(c, v) => write!(f, "{c} = {}", value(v)),
+            Predicate::NotEq(c, v) => write!(f, "{c} != {}", value(v)),
+            Predicate::Lt(c, v) => write!(f, "{c} < {}", value(v)),
+            Predicate::LtEq(c, v) => write!(f, "{c} <= {}", value(v)),
+            Predicate::Gt(c, v) => write!(f, "{c} > {}", value(v)),
+            Predicate::GtEq(c, v) => write!(f, "{c} >= {}", value(v)),
+            Predicate::Range { column, lower, upper } => write!(f, "{column} > {} AND {column} <= {}", value(lower), value(upper)),
+            Predicate::Contains(c, s) => write!(f, "{c} CONTAINS {}", value(&Field::String(s.clone()))),
+            Predicate::StartsWith(c, s) => write!(f, "{c} STARTS_WITH {}", value(&Field::String(s.clone()))),
+            Predicate::EndsWith(c, s) => write!(f, "{c} ENDS_WITH {}", value(&Field::String(s.clone()))),
+            Predicate::ILike(c, s) => write!(f, "{c} ILIKE {}", value(&Field::String(s.clone()))),
+            Predicate::Matches(c, s) => write!(f, "{c} MATCHES {}", value(&Field::String(s.clone()))),
+            Predicate::In(c, vs) => write!(f, "{c} IN ({})", vs.iter().map(value).collect::<Vec<_>>().join(", ")),
+            Predicate::IsNull(c) => write!(f, "{c} IS NULL"),
+            Predicate::IsNotNull(c) => write!(f, "{c} IS NOT NULL"),
+            Predicate::And(ps) => f.write_str(&join(ps, " AND ")),
+            Predicate::Or(ps) => f.write_str(&join(ps, " OR ")),
+            Predicate::Not(p) => write!(f, "NOT ({p})"),
+        }
+    }
+}
+
+/// Lowercased for comparing without case, borrowed when it already is.
+fn fold(s: &str) -> Cow<'_, str> {
+    match s.chars().any(char::is_uppercase) {
+        true => Cow::Owned(s.to_lowercase()),
+        false => Cow::Borrowed(s),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum LikeToken {
+    /// `%`
+    AnyRun,
+    /// `_`
+    AnyChar,
+    Char(char),
+}
+
+fn like_pattern(pattern: &str) -> Vec<LikeToken> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => LikeToken::AnyRun,
+            '_' => LikeToken::AnyChar,
+            '\\' => LikeToken::Char(chars.next().unwrap_or('\\')),
+            c => LikeToken::Char(c),
+        });
+    }
+
+    tokens
+}
+
+/// Whether all of `text` matches `pattern`. On a mismatch after a `%` the `%`
+/// takes one more char and matching resumes, which is enough as any later `%`
+/// can absorb whatever the earlier one would have.
+fn like(text: &str, pattern: &[LikeToken]) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(LikeToken::AnyRun) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(LikeToken::AnyChar) => {
+                t += 1;
+                p += 1;
+            }
+            Some(LikeToken::Char(c)) if *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            _ => match backtrack {
+                Some((run, from)) => {
+                    backtrack = Some((run, from + 1));
+                    p = run + 1;
+                    t = from + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|token| *token == LikeToken::AnyRun)
+}
+
+/// `Predicate` with columns resolved to indices.
+pub(crate) enum Bound {
+    Eq(usize, Field),
+    NotEq(usize, Field),
+    Lt(usize, Field),
+    LtEq(usize, Field),
+    Gt(usize, Field),
+    GtEq(usize, Field),
+    Range(usize, Field, Field),
+    Contains(usize, String),
+    StartsWith(usize, String),
+    EndsWith(usize, String),
+    ILike(usize, Vec<LikeToken>),
+    Matches(usize, Regex),
+    In(usize, Vec<Field>),
+    IsNull(usize),
+    And(Vec<Bound>),
+    Or(Vec<Bound>),
+    Not(Box<Bound>),
+}
+
+impl Bound {
+    /// The column a predicate on a single column looks at, `None` for `And`, `Or` and `Not`.
+    fn column(&self) -> Option<usize> {
+        match self {
+            Bound::Eq(col, _)
+            | Bound::NotEq(col, _)
+            | Bound::Lt(col, _)
+            | Bound::LtEq(col, _)
+            | Bound::Gt(col, _)
+            | Bound::GtEq(col, _)
+            | Bound::Range(col, _, _)
+            | Bound::Contains(col, _)
+            | Bound::StartsWith(col, _)
+            | Bound::EndsWith(col, _)
+            | Bound::ILike(col, _)
+            | Bound::Matches(col, _)
+            | Bound::In(col, _)
+            | Bound::IsNull(col) => Some(*col),
+            Bound::And(_) | Bound::Or(_) | Bound::Not(_) => None,
+        }
+    }
+
+    /// Whether `field` of the column passes. Only for single column predicates.
+    fn test(&self, field: &Field) -> bool {
+        // comparisons are only defined between two non-null values
+        let cmp = |val: &Field, test: fn(&Field, &Field) -> bool| !field.is_null() && !val.is_null() && test(field, val);
+
+        match self {
+            Bound::Eq(_, val) => field == val,
+            Bound::NotEq(_, val) => cmp(val, |a, b| a != b),
+            Bound::Lt(_, val) => cmp(val, |a, b| a < b),
+            Bound::LtEq(_, val) => cmp(val, |a, b| a <= b),
+            Bound::Gt(_, val) => cmp(val, |a, b| a > b),
+            Bound::GtEq(_, val) => cmp(val, |a, b| a >= b),
+            Bound::Range(_, lower, upper) => cmp(lower, |a, b| a > b) && cmp(upper, |a, b| a <= b),
+            Bound::Contains(_, needle) => matches!(field, Field::String(s) if fold(s).contains(needle.as_str())),
+            Bound::StartsWith(_, prefix) => matches!(field, Field::String(s) if fold(s).starts_with(prefix.as_str())),
+            Bound::EndsWith(_, suffix) => matches!(field, Field::String(s) if fold(s).ends_with(suffix.as_str())),
+            Bound::ILike(_, pattern) => matches!(field, Field::String(s) if like(&fold(s), pattern)),
+            Bound::Matches(_, regex) => matches!(field, Field::String(s) if regex.is_match(s)),
+            Bound::In(_, vals) => !field.is_null() && vals.contains(field),
+            Bound::IsNull(_) => field.is_null(),
+            Bound::And(_) | Bound::Or(_) | Bound::Not(_) => false,
+        }
+    }
+
+    /// Whether `row` of `cols` matches.
+    pub(crate) fn eval(&self, cols: &[Column], row: usize) -> bool {
+        match self {
+            Bound::And(all) => all.iter().all(|p| p.eval(cols, row)),
+            Bound::Or(any) => any.iter().any(|p| p.eval(cols, row)),
+            Bound::Not(p) => !p.eval(cols, row),
+            leaf => leaf.column().is_some_and(|col| leaf.test(&cols[col].get(row))),
+        }
+    }
+
+    /// Whether each of the first `len` rows of `cols` matches, going through a
+    /// column at a time.
+    pub(crate) fn select(&self, cols: &[Column], len: usize) -> Vec<bool> {
+        let combine = |parts: &[Bound], start: bool, op: fn(bool, bool) -> bool| {
+            parts.iter().fold(vec![start; len], |mut acc, p| {
+                acc.iter_mut().zip(p.select(cols, len)).for_each(|(a, b)| *a = op(*a, b));
+                acc
+            })
+        };
+
+        let col = match self {
+            Bound::And(all) => return combine(all, true, |a, b| a && b),
+            Bound::Or(any) => return combine(any, false, |a, b| a || b),
+            Bound::Not(p) => return p.select(cols, len).into_iter().map(|b| !b).collect(),
+            leaf => match leaf.column() {
+                Some(col) => &cols[col],
+                None => return vec![false; len],
+            },
+        };
+
+        let null = self.test(&Field::Null);
+        let valid = col.validity();
+
+        match (self, col.values()) {
+            // equality with a string is equality of symbols, and nothing equals
+            // a string no column has
+            (Bound::Eq(_, Field::String(s)), Values::Symbols(v)) => match Symbol::lookup(s) {
+                Some(symbol) => (0..len).map(|i| valid.get(i) && v[i] == symbol).collect(),
+                None => vec![false; len],
+            },
+            (Bound::In(_, vals), Values::Symbols(v)) if vals.iter().all(|f| matches!(f, Field::String(_))) => {
+                let symbols: HashSet<Symbol> = vals.iter().filter_map(|f| match f {
+                    Field::String(s) => Symbol::lookup(s),
+                    _ => None,
+                }).collect();
+                (0..len).map(|i| valid.get(i) && symbols.contains(&v[i])).collect()
+            }
+            // every distinct string is tested once, other rows only look up theirs
+            (_, Values::Symbols(v)) => {
+                let mut tested: HashMap<Symbol, bool> = HashMap::new();
+                (0..len)
+                    .map(|i| match valid.get(i) {
+                        true => *tested.entry(v[i]).or_insert_with(|| self.test(&Field::String(v[i].as_str().to_owned()))),
+                        false => null,
+                    })
+                    .collect()
+            }
+            (_, Values::Number(v)) => (0..len).map(|i| if valid.get(i) { self.test(&Field::Number(v[i])) } else { null }).collect(),
+            (_, Values::Date(v)) => (0..len).map(|i| if valid.get(i) { self.test(&Field::Date(v[i])) } else { null }).collect(),
+            (_, Values::Bool(v)) => (0..len).map(|i| if valid.get(i) { self.test(&Field::Bool(v[i])) } else { null }).collect(),
+            (_, Values::Empty) => vec![null; len],
+            (_, Values::String(_) | Values::Mixed(_)) => (0..len).map(|i| self.test(&col.get(i))).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(text: &str, pattern: &str) -> bool {
+        like(text, &like_pattern(pattern))
+    }
+
+    #[test]
+    fn like_wildcards() {
+        assert!(matches("abc", "abc"));
+        assert!(!matches("abcd", "abc"));
+        assert!(matches("abc", "a_c"));
+        assert!(!matches("ac", "a_c"));
+        assert!(matches("", "%"));
+        assert!(matches("abc", "%"));
+        assert!(matches("abc", "a%"));
+        assert!(matches("abc", "%c"));
+        assert!(matches("abc", "%b%"));
+        assert!(!matches("abc", "%d%"));
+        assert!(matches("abcbd", "a%b_"));
+        assert!(matches("mississippi", "%iss%ppi"));
+        assert!(!matches("mississippi", "%iss%ppix"));
+    }
+
+    #[test]
+    fn like_escapes() {
+        assert!(matches("100%", r"100\%"));
+        assert!(!matches("1000", r"100\%"));
+        assert!(matches("a_b", r"a\_b"));
+        assert!(!matches("axb", r"a\_b"));
+        assert!(matches("a\\", "a\\"));
+    }
+}
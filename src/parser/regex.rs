@@ -0,0 +1,581 @@
+use std::fmt::{self, Display, Formatter};
+
+/// A regular expression for `Predicate::Matches`. Supports literals, `.`,
+/// `[a-z]` and `[^..]` classes, `\d \w \s` and their negations, `^ $`, groups,
+/// `|` and the `* + ? {n} {n,} {n,m}` repetitions.
+///
+/// Matching runs all alternatives side by side over the text once, so it takes
+/// time linear in the text whatever the pattern.
+#[derive(Clone, Debug)]
+pub struct Regex {
+    insts: Vec<Inst>,
+    ignore_case: bool,
+}
+
+#[derive(Debug)]
+pub struct RegexError {
+    /// char offset into the pattern
+    pub pos: usize,
+    pub message: &'static str,
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at character {}", self.message, self.pos + 1)
+    }
+}
+
+/// `{n,m}` repeats are unrolled, this keeps that from getting out of hand
+const MAX_REPEAT: u32 = 1000;
+/// Repeats inside repeats multiply, so the whole program is capped too
+const MAX_INSTS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn digit() -> Vec<(char, char)> {
+        vec![('0', '9')]
+    }
+
+    fn word() -> Vec<(char, char)> {
+        vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]
+    }
+
+    fn space() -> Vec<(char, char)> {
+        vec![(' ', ' '), ('\t', '\r')]
+    }
+
+    fn contains(&self, c: char, ignore_case: bool) -> bool {
+        let in_ranges = |c: char| self.ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi);
+
+        let found = in_ranges(c) || (ignore_case && (c.to_lowercase().any(in_ranges) || c.to_uppercase().any(in_ranges)));
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32> },
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn error(&self, message: &'static str) -> RegexError {
+        RegexError { pos: self.at, message }
+    }
+
+    fn alternation(&mut self) -> Result<Node, RegexError> {
+        let mut alts = vec![self.concat()?];
+        while self.eat('|') {
+            alts.push(self.concat()?);
+        }
+
+        Ok(if alts.len() == 1 { alts.pop().unwrap_or(Node::Empty) } else { Node::Alt(alts) })
+    }
+
+    fn concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|') | Some(')')) {
+            nodes.push(self.repeat()?);
+        }
+
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap_or(Node::Empty),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    /// The digits of `{n,m}`, `None` when there are none.
+    fn number(&mut self) -> Option<u32> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect::<String>().parse().ok()
+    }
+
+    /// `{n}`, `{n,}` or `{n,m}`, anything else leaves the `{` a plain char.
+    fn braces(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let open = self.at;
+        self.at += 1;
+
+        let Some(min) = self.number() else {
+            self.at = open;
+            return Ok(None);
+        };
+        let max = match self.eat(',') {
+            true => self.number(),
+            false => Some(min),
+        };
+        if !self.eat('}') {
+            self.at = open;
+            return Ok(None);
+        }
+
+        if max.is_some_and(|max| max < min) {
+            return Err(RegexError { pos: open, message: "repeat range is backwards" });
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(RegexError { pos: open, message: "repeat count is over 1000" });
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn repeat(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.atom()?;
+
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.braces()? {
+                    Some(range) => {
+                        node = Node::Repeat { node: Box::new(node), min: range.0, max: range.1 };
+                        // lazy repeats match the same text, only what they capture differs
+                        self.eat('?');
+                        continue;
+                    }
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            self.at += 1;
+            self.eat('?');
+
+            node = Node::Repeat { node: Box::new(node), min, max };
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or_else(|| self.error("pattern ends with a lone \\"))?;
+        self.at += 1;
+
+        Ok(match c {
+            'd' => Node::Class(Class { ranges: Class::digit(), negated: false }),
+            'D' => Node::Class(Class { ranges: Class::digit(), negated: true }),
+            'w' => Node::Class(Class { ranges: Class::word(), negated: false }),
+            'W' => Node::Class(Class { ranges: Class::word(), negated: true }),
+            's' => Node::Class(Class { ranges: Class::space(), negated: false }),
+            'S' => Node::Class(Class { ranges: Class::space(), negated: true }),
+            'n' => Node::Char('\n'),
+            't' => Node::Char('\t'),
+            c if c.is_ascii_alphanumeric() => return Err(RegexError { pos: self.at - 1, message: "unknown escape" }),
+            c => Node::Char(c),
+        })
+    }
+
+    fn class_char(&mut self) -> Result<char, RegexError> {
+        match self.peek() {
+            None => Err(self.error("unclosed [")),
+            Some('\\') => {
+                self.at += 1;
+                let c = match self.peek() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(c) if !c.is_ascii_alphanumeric() => c,
+                    _ => return Err(self.error("unknown escape in []")),
+                };
+                self.at += 1;
+                Ok(c)
+            }
+            Some(c) => {
+                self.at += 1;
+                Ok(c)
+            }
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+
+        // a ] right after the [ is a plain char
+        let mut first = true;
+        while first || !self.eat(']') {
+            first = false;
+
+            if self.peek() == Some('\\') {
+                let shorthand = match self.chars.get(self.at + 1) {
+                    Some('d') => Some(Class::digit()),
+                    Some('w') => Some(Class::word()),
+                    Some('s') => Some(Class::space()),
+                    _ => None,
+                };
+                if let Some(extra) = shorthand {
+                    self.at += 2;
+                    ranges.extend(extra);
+                    continue;
+                }
+            }
+
+            let lo = self.class_char()?;
+            let hi = match self.peek() == Some('-') && self.chars.get(self.at + 1).is_some_and(|c| *c != ']') {
+                true => {
+                    self.at += 1;
+                    self.class_char()?
+                }
+                false => lo,
+            };
+
+            if hi < lo {
+                return Err(self.error("class range is backwards"));
+            }
+            ranges.push((lo, hi));
+        }
+
+        Ok(Node::Class(Class { ranges, negated }))
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or_else(|| self.error("expected more pattern"))?;
+        self.at += 1;
+
+        Ok(match c {
+            '(' => {
+                // groups don't capture, so (?:..) is the same thing
+                if self.peek() == Some('?') && self.chars.get(self.at + 1) == Some(&':') {
+                    self.at += 2;
+                }
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    return Err(self.error("unclosed ("));
+                }
+                inner
+            }
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '[' => self.class()?,
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => return Err(RegexError { pos: self.at - 1, message: "nothing to repeat" }),
+            c => Node::Char(c),
+        })
+    }
+}
+
+/// Instructions `compile` makes of `node`, saturating rather than overflowing.
+fn size(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Char(_) | Node::Any | Node::Class(_) | Node::Start | Node::End => 1,
+        Node::Concat(nodes) => nodes.iter().fold(0, |n, node| n.saturating_add(size(node))),
+        Node::Alt(alts) => alts.iter().fold(2 * (alts.len() - 1), |n, alt| n.saturating_add(size(alt))),
+        Node::Repeat { node, min, max } => {
+            let one = size(node);
+            let optional = match max {
+                None => one.saturating_add(2),
+                Some(max) => (*max - *min) as usize * one.saturating_add(1),
+            };
+            (*min as usize).saturating_mul(one).saturating_add(optional)
+        }
+    }
+}
+
+fn compile(node: &Node, insts: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => insts.push(Inst::Char(*c)),
+        Node::Any => insts.push(Inst::Any),
+        Node::Class(class) => insts.push(Inst::Class(class.clone())),
+        Node::Start => insts.push(Inst::Start),
+        Node::End => insts.push(Inst::End),
+        Node::Concat(nodes) => nodes.iter().for_each(|n| compile(n, insts)),
+        Node::Alt(alts) => {
+            // split to each alternative in turn, every one jumping past the rest when done
+            let mut jumps = Vec::new();
+            for (i, alt) in alts.iter().enumerate() {
+                let split = insts.len();
+                if i + 1 < alts.len() {
+                    insts.push(Inst::Split(split + 1, 0));
+                }
+                compile(alt, insts);
+                if i + 1 < alts.len() {
+                    jumps.push(insts.len());
+                    insts.push(Inst::Jmp(0));
+                    let next = insts.len();
+                    insts[split] = Inst::Split(split + 1, next);
+                }
+            }
+            let end = insts.len();
+            for jump in jumps {
+                insts[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, insts);
+            }
+
+            match max {
+                None => {
+                    let split = insts.len();
+                    insts.push(Inst::Split(split + 1, 0));
+                    compile(node, insts);
+                    insts.push(Inst::Jmp(split));
+                    let end = insts.len();
+                    insts[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(insts.len());
+                        insts.push(Inst::Split(insts.len() + 1, 0));
+                        compile(node, insts);
+                    }
+                    let end = insts.len();
+                    for split in splits {
+                        insts[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self, RegexError> {
+        let mut parser = Parser { chars: pattern.chars().collect(), at: 0 };
+
+        let node = parser.alternation()?;
+        if parser.at < parser.chars.len() {
+            return Err(parser.error("unmatched )"));
+        }
+
+        if size(&node) >= MAX_INSTS {
+            return Err(RegexError { pos: 0, message: "pattern repeats too much" });
+        }
+
+        let mut insts = Vec::new();
+        compile(&node, &mut insts);
+        insts.push(Inst::Match);
+
+        Ok(Regex { insts, ignore_case })
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    /// Follows jumps, splits and assertions from `pc`, adding every thread that
+    /// ends up waiting on a char. Returns whether one reached `Match`. The
+    /// paths are walked off `stack` rather than by recursion, there can be as
+    /// many of them in a row as the program is long.
+    fn add_thread(&self, list: &mut Vec<usize>, seen: &mut [bool], stack: &mut Vec<usize>, pc: usize, at: usize, len: usize) -> bool {
+        stack.clear();
+        stack.push(pc);
+
+        while let Some(pc) = stack.pop() {
+            if seen[pc] {
+                continue;
+            }
+            seen[pc] = true;
+
+            match &self.insts[pc] {
+                Inst::Jmp(to) => stack.push(*to),
+                Inst::Split(a, b) => stack.extend([*b, *a]),
+                Inst::Start if at == 0 => stack.push(pc + 1),
+                Inst::End if at == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Match => return true,
+                _ => list.push(pc),
+            }
+        }
+
+        false
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let len = chars.len();
+
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut seen = vec![false; self.insts.len()];
+        let mut stack = Vec::new();
+
+        for at in 0..=len {
+            // a match may start at any position
+            if self.add_thread(&mut current, &mut seen, &mut stack, 0, at, len) {
+                return true;
+            }
+
+            let Some(&c) = chars.get(at) else {
+                return false;
+            };
+
+            seen.iter_mut().for_each(|s| *s = false);
+            for &pc in &current {
+                let step = match &self.insts[pc] {
+                    Inst::Char(want) => self.char_eq(c, *want),
+                    Inst::Any => c != '\n',
+                    Inst::Class(class) => class.contains(c, self.ignore_case),
+                    _ => false,
+                };
+
+                if step && self.add_thread(&mut next, &mut seen, &mut stack, pc + 1, at + 1, len) {
+                    return true;
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern, false).unwrap().is_match(text)
+    }
+
+    fn error_at(pattern: &str) -> usize {
+        Regex::new(pattern, false).unwrap_err().pos
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^abc$", "abc"));
+        assert!(!matches("^abc$", "abcd"));
+        assert!(!matches("^abc", "xabc"));
+        assert!(matches("bc$", "abc"));
+        assert!(matches("^$", ""));
+        assert!(matches("abc", "xxabcxx"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("^[a-c]+$", "abcab"));
+        assert!(!matches("^[a-c]+$", "abd"));
+        assert!(matches("^[]x]$", "]"));
+        assert!(matches("^[a-]$", "-"));
+        assert!(matches(r"^\d{4}-\d\d$", "2020-01"));
+        assert!(matches(r"^[\w.]+$", "a_b.c9"));
+        assert!(matches(r"^a\sb$", "a\tb"));
+        assert!(!matches("^.$", "\n"));
+    }
+
+    #[test]
+    fn negated_classes() {
+        assert!(matches("^[^0-9]+$", "abc"));
+        assert!(!matches("^[^0-9]+$", "ab1"));
+        assert!(matches(r"^\D\W\S$", "a!b"));
+        assert!(!matches(r"\D", "123"));
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(matches("^(cat|dog)s?$", "dogs"));
+        assert!(matches("^(cat|dog)s?$", "cat"));
+        assert!(!matches("^(cat|dog)s?$", "cow"));
+        assert!(matches("^a|b$", "ax"));
+        assert!(matches("^(?:x|y|z)+$", "xyzzy"));
+    }
+
+    #[test]
+    fn empty_alternatives() {
+        assert!(matches("^(|a)b$", "b"));
+        assert!(matches("^(a|)b$", "ab"));
+        assert!(matches("^a(|)b$", "ab"));
+        assert!(matches("|", "anything"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn counted_repeats() {
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^a{1,3}$", "aaa"));
+        assert!(!matches("^a{1,3}$", "aaaa"));
+        assert!(matches("^(ab){0,2}c$", "ababc"));
+        assert!(matches("^a{,2}$", "a{,2}"));
+        assert!(matches("^x{1,2}?$", "xx"));
+    }
+
+    #[test]
+    fn case_folding() {
+        let regex = Regex::new("^the [a-z]+ ÅR$", true).unwrap();
+        assert!(regex.is_match("THE Beatles år"));
+        assert!(!Regex::new("^the$", false).unwrap().is_match("The"));
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error_at("ab(c"), 4);
+        assert_eq!(error_at("abc)"), 3);
+        assert_eq!(error_at("a[bc"), 4);
+        assert_eq!(error_at("a|*"), 2);
+        assert_eq!(error_at("*a"), 0);
+        assert_eq!(error_at("a{3,1}"), 1);
+        assert_eq!(error_at("ab{1001}"), 2);
+        assert_eq!(error_at(r"a\q"), 2);
+        assert_eq!(error_at("[z-a]"), 4);
+        assert_eq!(error_at("ab\\"), 3);
+    }
+
+    #[test]
+    fn nested_repeats_are_capped() {
+        assert!(Regex::new("((a{100}){100}){100}", false).is_err());
+        assert!(Regex::new("((a{1000}){1000}){1000}", false).is_err());
+        assert!(Regex::new(&"a{1000}".repeat(20), false).is_err());
+        assert!(Regex::new("(a{10}){10}", false).is_ok());
+    }
+
+    #[test]
+    fn long_empty_paths() {
+        // every optional a can be skipped, one long chain of splits
+        let regex = Regex::new("^(?:a?){999}b$", false).unwrap();
+        assert!(regex.is_match("b"));
+        assert!(regex.is_match(&format!("{}b", "a".repeat(999))));
+        assert!(!regex.is_match(&format!("{}b", "a".repeat(1000))));
+    }
+}
@@ -1,9 +1,16 @@
-use std::{collections::HashMap, fmt::{Debug, Display, Write}, io::Read, ops::Range};
+use std::{cmp::Ordering, collections::HashMap, fmt::{Debug, Display}, ops::Range};
 
-use super::parse::DateTime;
+use super::{aggregate::{Accumulator, Aggregate}, column::{Column, Values}, expr::Expr, intern::Symbol, parse::DateTime, predicate::Predicate, tz::TimeZone};
 
-#[derive(PartialEq, PartialOrd, Clone, Debug, Hash, Eq)]
+/// `Null` is declared first so it orders before every other value. Filters
+/// comparing with `<`/`>` never match a null, only `field_is(.., &Field::Null)`
+/// and `is_null` do. All nulls of a column form a single group.
+///
+/// The ordering is total: fields of different kinds order by the kind, in
+/// declaration order.
+#[derive(PartialEq, PartialOrd, Ord, Clone, Debug, Hash, Eq)]
 pub enum Field {
+    Null,
     Date(DateTime),
     String(String),
     Number(u64),
@@ -13,6 +20,7 @@ pub enum Field {
 impl Display for Field {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let as_str = match self {
+            Field::Null => "null",
             Field::String(s) => s,
             Field::Number(n) => &n.to_string(),
             Field::Date(d) => &format!("{}", d),
@@ -23,6 +31,18 @@ impl Display for Field {
     }
 }
 
+impl Field {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Field::Null)
+    }
+}
+
+impl<T: Into<Field>> From<Option<T>> for Field {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Field::Null, Into::into)
+    }
+}
+
 impl From<String> for Field {
     fn from(value: String) -> Self {
         Field::String(value)
@@ -65,46 +85,130 @@ impl Display for Row {
     }
 }
 
+/// One key of `Table::sort_by`. Nulls go last in either direction unless
+/// `nulls_first` is set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+    pub nulls_first: bool
+}
+
+impl SortKey {
+    pub fn asc(column: &str) -> Self {
+        SortKey { column: column.to_owned(), descending: false, nulls_first: false }
+    }
+
+    pub fn desc(column: &str) -> Self {
+        SortKey { column: column.to_owned(), descending: true, nulls_first: false }
+    }
+
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls_first = true;
+        self
+    }
+
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls_first = false;
+        self
+    }
+
+    /// Order of two values of the key's column, `None` being null.
+    fn compare<T: Ord>(&self, a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) if self.nulls_first => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) if self.nulls_first => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) if self.descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b)
+        }
+    }
+}
+
 pub static BIG_HISTORY_TABLE: [&str; 21] = ["time", "username", "platform", "msplayed", "country", "ip_addr", "user_agent", "song", "artist", "album", "track_uri", "episode_name", "episode_show_name", "episode_uri", "reason_start", "reason_end", "shuffle", "skipped", "offline", "offline_timestamp", "incognito_mode"];
 
+/// Text columns whose values repeat with every play, kept as `Symbol`s. Those
+/// with a value for nearly every play, like addresses and uris, are left out
+/// as interned strings are kept until the process ends.
+pub static INTERNED_COLUMNS: [&str; 10] = ["username", "platform", "country", "song", "artist", "album", "episode_name", "episode_show_name", "reason_start", "reason_end"];
+
+/// Stored a column at a time, see `Column`. `header` maps names to columns,
+/// leaving out the ones dropped by `select`.
 pub struct Table {
     pub header: Vec<(String, usize)>,
-    pub rows: Vec<Row>
+    columns: Vec<Column>,
+    len: usize
 }
 
+#[derive(Debug)]
 pub enum DataErrors {
     NotFound(String),
-    TooManyValues
+    TooManyValues,
+    WrongType(String),
+    InvalidPattern(String),
+    Overflow(String)
 }
 
-impl Debug for DataErrors {
+impl Display for DataErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NotFound(s) => f.write_str(s),
-            Self::TooManyValues => f.write_str("got too many values")
+            Self::TooManyValues => f.write_str("got too many values"),
+            Self::WrongType(s) => f.write_str(s),
+            Self::InvalidPattern(s) => f.write_str(s),
+            Self::Overflow(s) => f.write_str(s)
         }
     }
 }
 
 impl Table {
     pub fn new<const T: usize>(header: [&str; T]) -> Self {
+        Table::with_header(header.iter().map(|name| name.to_string()).collect())
+    }
 
-        let mut v: Vec<(String,usize)> = Vec::new();
+    pub fn with_header(names: Vec<String>) -> Self {
+        let columns = names.iter().map(|name| Column::new(INTERNED_COLUMNS.contains(&name.as_str()))).collect();
 
-        for i in 0..header.len() {
-            v.push((header[i].to_string(), i));
+        Table {
+            header: names.into_iter().enumerate().map(|(i, name)| (name, i)).collect(),
+            columns,
+            len: 0
         }
+    }
 
-        Table {
-            header: Vec::from(v),
-            rows: Vec::new()
+    /// A table of `columns` as they are, `header` mapping names to them like
+    /// `Table::header` does.
+    pub fn from_columns(header: Vec<(String, usize)>, columns: Vec<Column>) -> Result<Self, DataErrors> {
+        let len = columns.first().map_or(0, Column::len);
+        if columns.iter().any(|c| c.len() != len) || header.iter().any(|(_, col)| *col >= columns.len()) {
+            return Err(DataErrors::TooManyValues);
         }
+
+        Ok(Table { header, columns, len })
     }
 
-    pub fn insert<const T: usize>(&mut self, row: [Field; T]) -> Result<(), DataErrors> {
-        if row.len() != self.header.len() { return Err(DataErrors::TooManyValues) }
-        //println!("INSERT: {:?}", row);
-        Ok(self.rows.push(Row { fields: Vec::from(row) }))
+    pub fn insert<R: Into<Vec<Field>>>(&mut self, row: R) -> Result<(), DataErrors> {
+        let fields = row.into();
+        if fields.len() != self.columns.len() { return Err(DataErrors::TooManyValues) }
+
+        for (column, field) in self.columns.iter_mut().zip(fields) {
+            column.push(field);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Adds the rows of `other`, which has to have been made with the same header.
+    pub fn append(&mut self, other: Table) -> Result<(), DataErrors> {
+        if other.columns.len() != self.columns.len() { return Err(DataErrors::TooManyValues) }
+
+        for (column, other) in self.columns.iter_mut().zip(other.columns) {
+            column.append(other);
+        }
+        self.len += other.len;
+        Ok(())
     }
 
     pub fn get_col(&self, name: &str) -> Result<usize, DataErrors> {
@@ -115,96 +219,269 @@ impl Table {
             }
         }
 
-        return Err(DataErrors::NotFound(format!("No such column '{}'", name)))
+        Err(DataErrors::NotFound(format!("No such column '{}'", name)))
     }
 
-    pub fn field_is(mut self, field: &str, match_val: &Field) -> Result<Self, DataErrors> {
-        let col = self.get_col(field)?;
+    /// The column `get_col` gave `col` for.
+    pub fn column(&self, col: usize) -> &Column {
+        &self.columns[col]
+    }
 
-        self.rows = self.rows.into_iter().filter(|x| {
-            &x.fields[col] == match_val
-        }).collect();
+    /// Every column, including those dropped by `select`.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
 
-        Ok(self)
+    pub fn get(&self, row: usize, col: usize) -> Field {
+        self.columns[col].get(row)
     }
 
-    pub fn field_is_greater_than(mut self, field: &str, match_val: &Field) -> Result<Self, DataErrors> {
-        let col = self.get_col(field)?;
+    /// Every field of `row`, including those of columns dropped by `select`,
+    /// so `fields` is indexed like the columns.
+    pub fn row(&self, row: usize) -> Row {
+        Row { fields: self.columns.iter().map(|c| c.get(row)).collect() }
+    }
 
-        self.rows = self.rows.into_iter().filter(|x| {
-            &x.fields[col] > match_val
-        }).collect();
+    pub fn rows(&self) -> impl Iterator<Item = Row> + '_ {
+        (0..self.len).map(|i| self.row(i))
+    }
 
-        Ok(self)
+    fn retain(&mut self, keep: &[bool]) {
+        for column in &mut self.columns {
+            column.retain(keep);
+        }
+        self.len = keep.iter().filter(|k| **k).count();
     }
 
-    pub fn field_is_less_than(mut self, field: &str, match_val: &Field) -> Result<Self, DataErrors> {
-        let col = self.get_col(field)?;
+    fn permute(&mut self, order: &[usize]) {
+        for column in &mut self.columns {
+            column.permute(order);
+        }
+    }
+
+    /// Keeps the rows matching `predicate`, testing a column at a time.
+    pub fn filter(mut self, predicate: &Predicate) -> Result<Self, DataErrors> {
+        let bound = predicate.bind(&self)?;
 
-        self.rows = self.rows.into_iter().filter(|x| {
-            &x.fields[col] < match_val
-        }).collect();
+        let keep = bound.select(&self.columns, self.len);
+        self.retain(&keep);
 
         Ok(self)
     }
 
-    pub fn field_in_range(mut self, field: &str, lower: &Field, upper: &Field) -> Result<Self, DataErrors> {
-        let col = self.get_col(field)?;
+    pub fn field_is(self, field: &str, match_val: &Field) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::Eq(field.to_owned(), match_val.clone()))
+    }
+
+    pub fn field_is_greater_than(self, field: &str, match_val: &Field) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::Gt(field.to_owned(), match_val.clone()))
+    }
+
+    pub fn field_is_less_than(self, field: &str, match_val: &Field) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::Lt(field.to_owned(), match_val.clone()))
+    }
+
+    pub fn field_in_range(self, field: &str, lower: &Field, upper: &Field) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::Range { column: field.to_owned(), lower: lower.clone(), upper: upper.clone() })
+    }
+
+    pub fn contains(self, field: &str, needle: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::Contains(field.to_owned(), needle.to_owned()))
+    }
+
+    pub fn starts_with(self, field: &str, prefix: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::StartsWith(field.to_owned(), prefix.to_owned()))
+    }
+
+    pub fn ends_with(self, field: &str, suffix: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::EndsWith(field.to_owned(), suffix.to_owned()))
+    }
+
+    pub fn ilike(self, field: &str, pattern: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::ILike(field.to_owned(), pattern.to_owned()))
+    }
+
+    pub fn matches(self, field: &str, pattern: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::Matches(field.to_owned(), pattern.to_owned()))
+    }
+
+    pub fn is_null(self, field: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::IsNull(field.to_owned()))
+    }
+
+    pub fn is_not_null(self, field: &str) -> Result<Self, DataErrors> {
+        self.filter(&Predicate::IsNotNull(field.to_owned()))
+    }
+
+    /// Adds a column `name` holding `expr` evaluated for every row, usable like
+    /// any parsed column afterwards.
+    pub fn with_column(mut self, name: &str, expr: &Expr) -> Result<Self, DataErrors> {
+        let bound = expr.bind(&self)?;
 
-        self.rows = self.rows.into_iter().filter(|x| {
-            &x.fields[col] > lower && &x.fields[col] <= upper
-        }).collect();
+        let mut column = Column::new(INTERNED_COLUMNS.contains(&name));
+        for row in 0..self.len {
+            column.push(bound.eval(&self.columns, row)?);
+        }
 
+        self.columns.push(column);
+        self.header.retain(|(n, _)| n != name);
+        self.header.push((name.to_owned(), self.columns.len() - 1));
         Ok(self)
     }
 
-    pub fn group_by(&self, field: &str) -> Result<Table, DataErrors> {
-        let col = self.get_col(field)?;
+    /// One row per distinct combination of `keys`, holding the keys followed by
+    /// `aggs`. Groups keep the order they first appear in. Without keys the
+    /// whole table is one group, so an empty table still gives a row.
+    pub fn group_by(&self, keys: &[&str], aggs: &[Aggregate]) -> Result<Table, DataErrors> {
+        let key_cols: Vec<usize> = keys.iter().map(|k| self.get_col(k)).collect::<Result<_, _>>()?;
+        let agg_cols: Vec<Option<usize>> = aggs.iter().map(|a| a.column().map(|c| self.get_col(c)).transpose()).collect::<Result<_, _>>()?;
 
-        let mut cache = HashMap::new();
+        // rows compare by the codes of their keys rather than the fields
+        let key_codes: Vec<Vec<u32>> = key_cols.iter().map(|c| self.columns[*c].codes()).collect();
 
-        for row in &self.rows {
-            let field = &row.fields[col];
+        let mut index: HashMap<Vec<u32>, usize> = HashMap::new();
+        // the first row of a group stands in for its keys
+        let mut groups: Vec<(usize, Vec<Accumulator>)> = Vec::new();
+        let mut group_of = Vec::with_capacity(self.len);
 
-            cache.entry(field.clone()).and_modify(|count| *count += 1).or_insert(1);
-        };
-        
-        let rows = cache.into_iter().map(|(x, y)| Row { fields: vec![ x.clone(), Field::Number(y as u64) ] }).collect();
+        for row in 0..self.len {
+            let key: Vec<u32> = key_codes.iter().map(|codes| codes[row]).collect();
 
-        let table = Table {
-            header: vec![(field.to_owned(), 0), ("COUNT".to_owned(), 1)],
-            rows: rows
-        };
-        
-        Ok(table)
+            group_of.push(*index.entry(key).or_insert_with(|| {
+                groups.push((row, aggs.iter().map(Accumulator::new).collect()));
+                groups.len() - 1
+            }));
+        }
+
+        for (a, col) in agg_cols.iter().enumerate() {
+            let Some(col) = col else {
+                for group in &group_of {
+                    groups[*group].1[a].add(&Field::Null)?;
+                }
+                continue;
+            };
+
+            let column = &self.columns[*col];
+            match column.values() {
+                // numbers are summed straight off the slice
+                Values::Number(v) => {
+                    for (row, group) in group_of.iter().enumerate() {
+                        let field = if column.validity().get(row) { Field::Number(v[row]) } else { Field::Null };
+                        groups[*group].1[a].add(&field)?;
+                    }
+                }
+                // a symbol's field is made once, not for every row holding it
+                Values::Symbols(v) => {
+                    let mut fields: HashMap<Symbol, Field> = HashMap::new();
+                    for (row, group) in group_of.iter().enumerate() {
+                        let field = match column.validity().get(row) {
+                            true => fields.entry(v[row]).or_insert_with(|| Field::String(v[row].as_str().to_owned())),
+                            false => &Field::Null,
+                        };
+                        groups[*group].1[a].add(field)?;
+                    }
+                }
+                _ => {
+                    for (row, group) in group_of.iter().enumerate() {
+                        groups[*group].1[a].add(&column.get(row))?;
+                    }
+                }
+            }
+        }
+
+        if groups.is_empty() && keys.is_empty() {
+            groups.push((0, aggs.iter().map(Accumulator::new).collect()));
+        }
+
+        let names = keys.iter().map(|k| k.to_string()).chain(aggs.iter().map(|a| a.to_string()));
+        let mut res = Table::with_header(names.collect());
+
+        for (first, accs) in groups {
+            let fields: Vec<Field> = key_cols.iter().map(|col| self.columns[*col].get(first))
+                .chain(accs.into_iter().map(Accumulator::finish))
+                .collect();
+            res.insert(fields)?;
+        }
+
+        Ok(res)
+    }
+
+    /// Distinct text values of `field` most like `query`, best first with their
+    /// score from 0 to 1. See `fuzzy::similarity`.
+    pub fn fuzzy_find(&self, field: &str, query: &str, limit: usize) -> Result<Vec<(String, f64)>, DataErrors> {
+        Ok(self.columns[self.get_col(field)?].fuzzy_find(query, limit))
     }
 
-    pub fn select<const T: usize>(self, cols: [&str; T]) -> Self {
+    /// Keeps only `cols`, in the order given. Unknown names are skipped.
+    pub fn select(self, cols: &[&str]) -> Self {
         Table {
-            header: self.header.into_iter().filter(|x| cols.contains(&x.0.as_str())).collect(),
-            rows: self.rows
+            header: cols.iter().filter_map(|c| self.header.iter().find(|x| x.0 == *c).cloned()).collect(),
+            columns: self.columns,
+            len: self.len
         }
     }
 
-    pub fn sort_by(mut self, field: &str) -> Result<Self, DataErrors> {
-        let col = self.get_col(field)?;
-        self.rows.sort_by(| a, b | a.fields[col].partial_cmp(&b.fields[col]).expect("CANT ORDER"));
+    /// Like `select`, showing each column under the name paired with it. The
+    /// header is built in one go, so names can be swapped or reused.
+    pub fn select_as(self, cols: &[(&str, &str)]) -> Self {
+        let header = cols
+            .iter()
+            .filter_map(|(c, shown)| self.header.iter().find(|x| x.0 == *c).map(|(_, col)| (shown.to_string(), *col)))
+            .collect();
 
-        let ordered = Table {
-            header: self.header,
-            rows: self.rows
-        };
+        Table { header, columns: self.columns, len: self.len }
+    }
+
+    /// Orders by the first key, ties by the next and so on. The sort is stable,
+    /// so rows equal on every key keep the order they had.
+    pub fn sort_by(mut self, keys: &[SortKey]) -> Result<Self, DataErrors> {
+        let cols: Vec<(usize, &SortKey)> = keys.iter().map(|k| self.get_col(&k.column).map(|c| (c, k))).collect::<Result<_, _>>()?;
+
+        // rows compare by the ranks of their values, worked out once per key
+        let ranks: Vec<Vec<u64>> = cols.iter().map(|(col, _)| self.columns[*col].ranks()).collect();
+        let rank = |col: usize, ranks: &[u64], row: usize| self.columns[col].validity().get(row).then_some(ranks[row]);
+
+        let mut order: Vec<usize> = (0..self.len).collect();
+        order.sort_by(| a, b | {
+            cols.iter().zip(&ranks)
+                .map(|((col, key), ranks)| key.compare(rank(*col, ranks, *a), rank(*col, ranks, *b)))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
 
-        Ok(ordered)
+        self.permute(&order);
+        Ok(self)
+    }
+
+    /// Moves every date from UTC to the wall clock in `tz`, so filters and
+    /// grouping see local times.
+    pub fn to_zone(mut self, tz: &TimeZone) -> Self {
+        for column in &mut self.columns {
+            column.map_dates(|date| date.to_zone(tz));
+        }
+        self
+    }
+
+    pub fn reverse(mut self) -> Self {
+        let order: Vec<usize> = (0..self.len).rev().collect();
+        self.permute(&order);
+        self
+    }
+
+    pub fn limit(mut self, n: usize) -> Self {
+        for column in &mut self.columns {
+            column.truncate(n);
+        }
+        self.len = self.len.min(n);
+        self
     }
 
     pub fn row_at(&self, index: usize) -> Option<Row> {
-        let first = match self.rows.get(index) {
-            Some(s) => s,
-            None => return None
-        };
+        if index >= self.len {
+            return None;
+        }
 
-        let fields: Vec<Field> = self.header.iter().map(|(_name, col)| first.fields[*col].clone()).collect();
+        let fields: Vec<Field> = self.header.iter().map(|(_name, col)| self.columns[*col].get(index)).collect();
 
         Some(Row {
             fields
@@ -214,9 +491,8 @@ impl Table {
     pub fn take(&self, range: Range<i32>) -> Vec<Row> {
         let mut res = Vec::new();
         for i in range {
-            match self.row_at(i as usize) {
-                Some(i) => res.push(i),
-                None => {}
+            if let Some(i) = self.row_at(i as usize) {
+                res.push(i);
             }
         }
 
@@ -228,18 +504,71 @@ impl Table {
     }
 
     pub fn len(&self) -> usize {
-        self.rows.len()
-    } 
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 
 }
 
 impl std::fmt::Display for Table {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for r in &self.rows {
-            let fields: Vec<&Field> = self.header.iter().map(|(_name, col)| &r.fields[*col]).collect();
-            let as_str: Vec<String> = fields.into_iter().map(|f| f.to_string()).collect();
+        for row in 0..self.len {
+            let as_str: Vec<String> = self.header.iter().map(|(_name, col)| self.columns[*col].get(row).to_string()).collect();
             f.write_str(&format!("{}\n", as_str.join("|")))?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(tbl: &Table, col: usize) -> Vec<Option<&str>> {
+        (0..tbl.len()).map(|row| tbl.column(col).get_str(row)).collect()
+    }
+
+    fn plays() -> Table {
+        let mut tbl = Table::new(["artist", "ip_addr", "msplayed"]);
+        for (artist, ip, ms) in [(Some("b"), "2", Some(5)), (None, "1", Some(5)), (Some("a"), "3", None), (Some("c"), "1", Some(1)), (Some("a"), "2", Some(7))] {
+            tbl.insert([artist.into(), ip.into(), ms.into()]).unwrap();
+        }
+        tbl
+    }
+
+    #[test]
+    fn sorts_symbols_by_their_text() {
+        let tbl = plays().sort_by(&[SortKey::asc("artist"), SortKey::desc("msplayed")]).unwrap();
+
+        assert!(matches!(tbl.column(0).values(), Values::Symbols(_)));
+        assert_eq!(strings(&tbl, 0), [Some("a"), Some("a"), Some("b"), Some("c"), None]);
+        assert_eq!(tbl.get(0, 2), Field::Number(7));
+        assert_eq!(tbl.get(1, 2), Field::Null);
+    }
+
+    #[test]
+    fn sorts_nulls_where_asked() {
+        let tbl = plays().sort_by(&[SortKey::desc("artist").nulls_first()]).unwrap();
+        assert_eq!(strings(&tbl, 0), [None, Some("c"), Some("b"), Some("a"), Some("a")]);
+
+        let tbl = plays().sort_by(&[SortKey::asc("msplayed"), SortKey::asc("ip_addr")]).unwrap();
+        assert_eq!(strings(&tbl, 1), [Some("1"), Some("1"), Some("2"), Some("2"), Some("3")]);
+    }
+
+    #[test]
+    fn high_cardinality_columns_are_not_interned() {
+        let tbl = plays();
+        assert!(tbl.column(0).is_interned());
+        assert!(!tbl.column(1).is_interned());
+        assert!(matches!(tbl.column(1).values(), Values::String(_)));
+    }
+
+    #[test]
+    fn aggregates_symbols() {
+        let tbl = plays().group_by(&[], &[Aggregate::Min("artist".to_owned()), Aggregate::Max("artist".to_owned()), Aggregate::CountDistinct("artist".to_owned())]).unwrap();
+        assert_eq!(tbl.row(0).fields, [Field::from("a"), Field::from("c"), Field::Number(3)]);
+    }
+}
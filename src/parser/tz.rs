@@ -0,0 +1,579 @@
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    fs,
+    path::PathBuf,
+};
+
+use super::parse::{days_from_civil, days_in_month, is_leap_year, DateTime};
+
+/// Where the IANA zones are looked up, `$TZDIR` takes precedence.
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+#[derive(Debug)]
+pub enum TimeZoneError {
+    Unknown(String),
+    /// the tzdata file exists but couldn't be read
+    Invalid(String, &'static str),
+}
+
+impl Display for TimeZoneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZoneError::Unknown(name) => write!(f, "unknown time zone '{name}', expected an offset like +02:00 or a zone like Europe/Stockholm"),
+            TimeZoneError::Invalid(name, why) => write!(f, "time zone '{name}' has invalid tzdata: {why}"),
+        }
+    }
+}
+
+/// What the exports' UTC times are converted to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeZone {
+    /// seconds east of UTC
+    Fixed(i32),
+    Zone(Zone),
+}
+
+impl TimeZone {
+    /// `UTC`, an offset like `+02:00`, `-0530` or `UTC+1`, or an IANA name like
+    /// `Europe/Stockholm`.
+    pub fn from_name(name: &str) -> Result<Self, TimeZoneError> {
+        if let Some(offset) = parse_fixed(name) {
+            return Ok(TimeZone::Fixed(offset));
+        }
+
+        Zone::load(name).map(TimeZone::Zone)
+    }
+
+    /// Seconds east of UTC at the UTC instant `timestamp`.
+    pub fn offset_at(&self, timestamp: i64) -> i32 {
+        match self {
+            TimeZone::Fixed(offset) => *offset,
+            TimeZone::Zone(zone) => zone.offset_at(timestamp),
+        }
+    }
+
+    /// Seconds east of UTC where the wall clock shows `local`, a timestamp of
+    /// local time. In the hour repeated when clocks go back the later offset wins.
+    pub fn offset_at_local(&self, local: i64) -> i32 {
+        self.offset_at(local - self.offset_at(local) as i64)
+    }
+}
+
+/// `+hh:mm` for `offset` seconds east of UTC.
+pub fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("{sign}{:0>2}:{:0>2}", offset.abs() / 3600, offset.abs() / 60 % 60)
+}
+
+/// `[+-]hh[[:]mm]`, the sign is required.
+pub fn parse_offset(s: &str) -> Option<i32> {
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() > 2 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 || !rest.bytes().all(|b| b.is_ascii_digit() || b == b':') {
+        return None;
+    }
+
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn parse_fixed(name: &str) -> Option<i32> {
+    let upper = name.to_ascii_uppercase();
+    let rest = upper.strip_prefix("UTC").or_else(|| upper.strip_prefix("GMT")).unwrap_or(&upper);
+
+    match rest {
+        "" | "Z" => Some(0),
+        offset => parse_offset(offset),
+    }
+}
+
+/// An IANA zone read from a TZif file, see RFC 8536.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    /// UTC instants the offset changes at, ascending
+    transitions: Vec<i64>,
+    /// index into `offsets` in effect from the matching transition
+    kinds: Vec<usize>,
+    offsets: Vec<i32>,
+    /// how offsets continue after the last transition
+    rule: Option<Rule>,
+}
+
+struct Bytes<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at.checked_add(n)?)?;
+        self.at += n;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+struct Header {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl Header {
+    fn read(bytes: &mut Bytes) -> Option<Self> {
+        if bytes.take(4)? != b"TZif" {
+            return None;
+        }
+        let version = bytes.take(1)?[0];
+        bytes.take(15)?;
+
+        let mut count = || bytes.u32().map(|n| n as usize);
+        Some(Header {
+            version,
+            isutcnt: count()?,
+            isstdcnt: count()?,
+            leapcnt: count()?,
+            timecnt: count()?,
+            typecnt: count()?,
+            charcnt: count()?,
+        })
+    }
+
+    /// Size of the data block following the header, `time` being 4 or 8.
+    fn data_len(&self, time: usize) -> usize {
+        self.timecnt * (time + 1) + self.typecnt * 6 + self.charcnt + self.leapcnt * (time + 4) + self.isstdcnt + self.isutcnt
+    }
+}
+
+impl Zone {
+    fn load(name: &str) -> Result<Self, TimeZoneError> {
+        let unknown = || TimeZoneError::Unknown(name.to_owned());
+
+        // names are relative to the zoneinfo directory and stay in it
+        if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            return Err(unknown());
+        }
+
+        let dir = env::var_os("TZDIR").map_or_else(|| PathBuf::from(ZONEINFO), PathBuf::from);
+        let data = fs::read(dir.join(name)).map_err(|_| unknown())?;
+
+        Zone::parse(name, &data)
+    }
+
+    fn parse(name: &str, data: &[u8]) -> Result<Self, TimeZoneError> {
+        let invalid = |why| TimeZoneError::Invalid(name.to_owned(), why);
+        let mut bytes = Bytes { data, at: 0 };
+
+        let mut header = Header::read(&mut bytes).ok_or_else(|| TimeZoneError::Unknown(name.to_owned()))?;
+        let mut time_size = 4;
+
+        // version 2 and later repeat everything with 64 bit times, which is the part to use
+        if header.version >= b'2' {
+            bytes.take(header.data_len(4)).ok_or_else(|| invalid("truncated"))?;
+            header = Header::read(&mut bytes).ok_or_else(|| invalid("missing the 64 bit header"))?;
+            time_size = 8;
+        }
+
+        let mut read = || -> Option<Zone> {
+            let transitions = (0..header.timecnt)
+                .map(|_| if time_size == 8 { bytes.i64() } else { bytes.i32().map(i64::from) })
+                .collect::<Option<Vec<_>>>()?;
+            let kinds: Vec<usize> = bytes.take(header.timecnt)?.iter().map(|k| *k as usize).collect();
+
+            let mut offsets = Vec::with_capacity(header.typecnt);
+            for _ in 0..header.typecnt {
+                offsets.push(bytes.i32()?);
+                // is_dst and the abbreviation index
+                bytes.take(2)?;
+            }
+
+            if kinds.iter().any(|k| *k >= offsets.len()) || offsets.is_empty() {
+                return None;
+            }
+
+            let rule = match header.version >= b'2' {
+                true => {
+                    bytes.take(header.data_len(8) - header.timecnt * 9 - header.typecnt * 6)?;
+                    let footer = std::str::from_utf8(bytes.data.get(bytes.at..)?).ok()?;
+                    Rule::parse(footer.trim_matches('\n'))
+                }
+                false => None,
+            };
+
+            Some(Zone { name: name.to_owned(), transitions, kinds, offsets, rule })
+        };
+
+        read().ok_or_else(|| invalid("truncated or inconsistent"))
+    }
+
+    fn offset_at(&self, timestamp: i64) -> i32 {
+        let after = self.transitions.partition_point(|t| *t <= timestamp);
+
+        match (after, &self.rule) {
+            // also when there are no transitions at all, only a rule
+            (n, Some(rule)) if n == self.transitions.len() => rule.offset_at(timestamp),
+            // before the first transition the first type applies
+            (0, _) => self.offsets[0],
+            (n, _) => self.offsets[self.kinds[n - 1]],
+        }
+    }
+}
+
+/// When a POSIX rule switches, relative to local time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuleDay {
+    /// `Jn`, 1 to 365 never counting February 29th
+    Julian(u16),
+    /// `n`, 0 to 365 counting February 29th
+    Ordinal(u16),
+    /// `Mm.w.d`, day `d` (0 is Sunday) of week `w` of month `m`, week 5 being the last
+    MonthWeekDay(u8, u8, u8),
+}
+
+impl RuleDay {
+    /// Days from 1970-01-01 to this day in `year`.
+    fn days(&self, year: u16) -> i64 {
+        let jan_first = days_from_civil(year as i64, 1, 1);
+
+        match *self {
+            RuleDay::Julian(n) => {
+                let leap_shift = (is_leap_year(year) && n > 59) as i64;
+                jan_first + n as i64 - 1 + leap_shift
+            }
+            RuleDay::Ordinal(n) => jan_first + n as i64,
+            RuleDay::MonthWeekDay(month, week, weekday) => {
+                let first = days_from_civil(year as i64, month, 1);
+                // 1970-01-01 was a Thursday, day 4 counting from Sunday
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first + (weekday as i64 - first_weekday).rem_euclid(7) + (week as i64 - 1) * 7;
+
+                while day >= first + days_in_month(year, month) as i64 {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+/// The POSIX TZ string at the end of a TZif file, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    /// seconds east of UTC, unlike the POSIX sign
+    std_offset: i32,
+    dst: Option<Dst>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Dst {
+    offset: i32,
+    /// days with the local time of day in seconds
+    start: (RuleDay, i32),
+    end: (RuleDay, i32),
+}
+
+struct RuleParser<'a> {
+    s: &'a str,
+}
+
+impl RuleParser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        match self.s.strip_prefix(c) {
+            Some(rest) => {
+                self.s = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn number(&mut self) -> Option<i32> {
+        let len = self.s.find(|c: char| !c.is_ascii_digit()).unwrap_or(self.s.len());
+        let (digits, rest) = self.s.split_at(len);
+        self.s = rest;
+        digits.parse().ok()
+    }
+
+    /// `CET` or `<+03>`
+    fn name(&mut self) -> Option<()> {
+        let len = match self.s.strip_prefix('<') {
+            Some(quoted) => quoted.find('>')? + 2,
+            None => self.s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(self.s.len()),
+        };
+        if len < 3 {
+            return None;
+        }
+        self.s = &self.s[len..];
+        Some(())
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, hours may go up to 167 for rule times
+    fn time(&mut self) -> Option<i32> {
+        let sign = if self.eat('-') { -1 } else { self.eat('+'); 1 };
+
+        let mut secs = self.number()? * 3600;
+        if self.eat(':') {
+            secs += self.number()? * 60;
+            if self.eat(':') {
+                secs += self.number()?;
+            }
+        }
+        Some(sign * secs)
+    }
+
+    fn day(&mut self) -> Option<RuleDay> {
+        if self.eat('J') {
+            let n = self.number()?;
+            return (1..=365).contains(&n).then_some(RuleDay::Julian(n as u16));
+        }
+        if self.eat('M') {
+            let month = self.number()?;
+            self.eat('.').then_some(())?;
+            let week = self.number()?;
+            self.eat('.').then_some(())?;
+            let weekday = self.number()?;
+
+            let valid = (1..=12).contains(&month) && (1..=5).contains(&week) && (0..=6).contains(&weekday);
+            return valid.then_some(RuleDay::MonthWeekDay(month as u8, week as u8, weekday as u8));
+        }
+
+        let n = self.number()?;
+        (0..=365).contains(&n).then_some(RuleDay::Ordinal(n as u16))
+    }
+
+    /// `day[/time]`, the time defaulting to 02:00
+    fn switch(&mut self) -> Option<(RuleDay, i32)> {
+        let day = self.day()?;
+        let time = if self.eat('/') { self.time()? } else { 2 * 3600 };
+        Some((day, time))
+    }
+}
+
+impl Rule {
+    fn parse(s: &str) -> Option<Self> {
+        let mut p = RuleParser { s };
+
+        p.name()?;
+        let std_offset = -p.time()?;
+
+        if p.s.is_empty() {
+            return Some(Rule { std_offset, dst: None });
+        }
+
+        p.name()?;
+        let dst_offset = match p.s.starts_with(',') || p.s.is_empty() {
+            true => std_offset + 3600,
+            false => -p.time()?,
+        };
+
+        // the US rules are the POSIX default
+        let (start, end) = match p.eat(',') {
+            true => {
+                let start = p.switch()?;
+                p.eat(',').then_some(())?;
+                (start, p.switch()?)
+            }
+            false => ((RuleDay::MonthWeekDay(3, 2, 0), 7200), (RuleDay::MonthWeekDay(11, 1, 0), 7200)),
+        };
+
+        p.s.is_empty().then_some(Rule { std_offset, dst: Some(Dst { offset: dst_offset, start, end }) })
+    }
+
+    fn offset_at(&self, timestamp: i64) -> i32 {
+        let Some(Dst { offset: dst_offset, start: (start_day, start_time), end: (end_day, end_time) }) = self.dst else {
+            return self.std_offset;
+        };
+
+        let year = match DateTime::from_timestamp(timestamp + self.std_offset as i64) {
+            Some(local) => local.year,
+            None => return self.std_offset,
+        };
+
+        // dst starts on standard time and ends on daylight time
+        let start = start_day.days(year) * 86_400 + start_time as i64 - self.std_offset as i64;
+        let end = end_day.days(year) * 86_400 + end_time as i64 - dst_offset as i64;
+
+        let in_dst = match start < end {
+            true => start <= timestamp && timestamp < end,
+            // southern hemisphere, dst spans the new year
+            false => !(end <= timestamp && timestamp < start),
+        };
+
+        if in_dst { dst_offset } else { self.std_offset }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STOCKHOLM: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+    const SYDNEY: &str = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+
+    fn ts(year: u16, month: u8, day: u8, hour: u8, minute: u8) -> i64 {
+        DateTime::new(year, month, day, hour, minute, 0).unwrap().timestamp()
+    }
+
+    /// A TZif file, version 1 unless there is a `footer`.
+    fn tzif(transitions: &[i64], kinds: &[u8], offsets: &[i32], footer: Option<&str>) -> Vec<u8> {
+        let block = |out: &mut Vec<u8>, version: u8, wide: bool| {
+            out.extend(b"TZif");
+            out.push(version);
+            out.extend([0; 15]);
+            for count in [0, 0, 0, transitions.len(), offsets.len(), 4] {
+                out.extend((count as u32).to_be_bytes());
+            }
+            for t in transitions {
+                match wide {
+                    true => out.extend(t.to_be_bytes()),
+                    false => out.extend((*t as i32).to_be_bytes()),
+                }
+            }
+            out.extend(kinds);
+            for offset in offsets {
+                out.extend(offset.to_be_bytes());
+                out.extend([0, 0]);
+            }
+            out.extend(b"CET\0");
+        };
+
+        let mut out = Vec::new();
+        match footer {
+            Some(footer) => {
+                block(&mut out, b'2', false);
+                block(&mut out, b'2', true);
+                out.extend(format!("\n{footer}\n").bytes());
+            }
+            None => block(&mut out, 0, false),
+        }
+        out
+    }
+
+    fn stockholm() -> TimeZone {
+        let transitions = [ts(2023, 3, 26, 1, 0), ts(2023, 10, 29, 1, 0)];
+        TimeZone::Zone(Zone::parse("Europe/Stockholm", &tzif(&transitions, &[1, 0], &[3600, 7200], Some(STOCKHOLM))).unwrap())
+    }
+
+    #[test]
+    fn transitions() {
+        let tz = stockholm();
+
+        assert_eq!(tz.offset_at(ts(2023, 3, 26, 0, 59)), 3600);
+        assert_eq!(tz.offset_at(ts(2023, 3, 26, 1, 0)), 7200);
+        assert_eq!(tz.offset_at(ts(2023, 10, 29, 0, 59)), 7200);
+        assert_eq!(tz.offset_at(ts(2023, 10, 29, 1, 0)), 3600);
+        // before the first transition
+        assert_eq!(tz.offset_at(ts(2000, 7, 1, 0, 0)), 3600);
+    }
+
+    #[test]
+    fn footer_rule_after_the_last_transition() {
+        let tz = stockholm();
+
+        assert_eq!(tz.offset_at(ts(2040, 1, 15, 12, 0)), 3600);
+        assert_eq!(tz.offset_at(ts(2040, 3, 25, 0, 59)), 3600);
+        assert_eq!(tz.offset_at(ts(2040, 3, 25, 1, 0)), 7200);
+        assert_eq!(tz.offset_at(ts(2040, 10, 28, 0, 59)), 7200);
+        assert_eq!(tz.offset_at(ts(2040, 10, 28, 1, 0)), 3600);
+    }
+
+    #[test]
+    fn southern_hemisphere() {
+        // Sydney's real file has transitions up to 2037, the rule has to do
+        // without any
+        let tz = TimeZone::Zone(Zone::parse("Australia/Sydney", &tzif(&[], &[], &[36000], Some(SYDNEY))).unwrap());
+
+        assert_eq!(tz.offset_at(ts(2023, 1, 15, 0, 0)), 39600);
+        assert_eq!(tz.offset_at(ts(2023, 7, 15, 0, 0)), 36000);
+        // 03:00 daylight time on the first Sunday of April, 02:00 standard time on the first of October
+        assert_eq!(tz.offset_at(ts(2023, 4, 1, 15, 59)), 39600);
+        assert_eq!(tz.offset_at(ts(2023, 4, 1, 16, 0)), 36000);
+        assert_eq!(tz.offset_at(ts(2023, 9, 30, 15, 59)), 36000);
+        assert_eq!(tz.offset_at(ts(2023, 9, 30, 16, 0)), 39600);
+    }
+
+    #[test]
+    fn version_1_files() {
+        let zone = Zone::parse("Old", &tzif(&[ts(2000, 1, 1, 0, 0)], &[1], &[0, 7200], None)).unwrap();
+
+        assert_eq!(zone.rule, None);
+        assert_eq!(zone.offset_at(ts(1999, 1, 1, 0, 0)), 0);
+        // the last type carries on without a rule
+        assert_eq!(zone.offset_at(ts(2040, 7, 1, 0, 0)), 7200);
+    }
+
+    #[test]
+    fn malformed_files() {
+        let good = tzif(&[ts(2023, 3, 26, 1, 0)], &[1], &[3600, 7200], Some(STOCKHOLM));
+
+        assert!(matches!(Zone::parse("x", b"not a zone file"), Err(TimeZoneError::Unknown(_))));
+        assert!(matches!(Zone::parse("x", &[]), Err(TimeZoneError::Unknown(_))));
+        for len in [50, 60, good.len() / 2, good.len() - STOCKHOLM.len() - 5] {
+            assert!(matches!(Zone::parse("x", &good[..len]), Err(TimeZoneError::Invalid(..))), "{len}");
+        }
+        // a transition to a type that isn't there
+        assert!(Zone::parse("x", &tzif(&[0], &[5], &[3600], None)).is_err());
+        // no types at all
+        assert!(Zone::parse("x", &tzif(&[], &[], &[], None)).is_err());
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(Rule::parse("UTC0"), Some(Rule { std_offset: 0, dst: None }));
+        assert_eq!(Rule::parse("<+0530>-5:30"), Some(Rule { std_offset: 19800, dst: None }));
+        assert_eq!(Rule::parse("EST5EDT").and_then(|r| r.dst).map(|d| d.offset), Some(-4 * 3600));
+        for bad in ["", "X1", "CET", "CET-1CEST,M13.5.0,M10.5.0", "CET-1CEST,M3.5.0", "CET-1CEST,M3.5.0,M10.5.0/3x"] {
+            assert_eq!(Rule::parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn repeated_and_skipped_local_hours() {
+        let tz = stockholm();
+
+        assert_eq!(tz.offset_at_local(ts(2023, 7, 1, 12, 0)), 7200);
+        assert_eq!(tz.offset_at_local(ts(2023, 1, 1, 12, 0)), 3600);
+        // 02:30 happens twice on 2023-10-29, the later one is standard time
+        assert_eq!(tz.offset_at_local(ts(2023, 10, 29, 2, 30)), 3600);
+        // and never on 2023-03-26, it's read as standard time, i.e. 03:30 daylight time
+        assert_eq!(tz.offset_at_local(ts(2023, 3, 26, 2, 30)), 3600);
+        assert_eq!(tz.offset_at_local(ts(2023, 3, 26, 3, 0)), 7200);
+    }
+
+    #[test]
+    fn fixed_offsets() {
+        assert_eq!(TimeZone::from_name("+02:00").unwrap(), TimeZone::Fixed(7200));
+        assert_eq!(TimeZone::from_name("UTC-0530").unwrap(), TimeZone::Fixed(-19800));
+        assert_eq!(TimeZone::from_name("utc").unwrap(), TimeZone::Fixed(0));
+        assert!(TimeZone::from_name("+15:00").is_err());
+        assert!(TimeZone::from_name("../etc/passwd").is_err());
+    }
+}
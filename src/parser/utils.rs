@@ -1,5 +1,5 @@
 use super::parse::DateTime;
 
 pub fn quick_date(year: u16, month: u8, day: u8) -> DateTime {
-    DateTime { day: day, month: month, year: year, minute: 0, hour: 0 }
+    DateTime { day, month, year, minute: 0, hour: 0, second: 0 }
 }
\ No newline at end of file
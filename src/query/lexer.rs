@@ -0,0 +1,108 @@
+use super::QueryError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    /// a bare word, keywords included; `"quoted"` words are always identifiers
+    Word(String),
+    QuotedIdent(String),
+    String(String),
+    Number(u64),
+    Comma,
+    Star,
+    LParen,
+    RParen,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    End,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// byte offset into the query
+    pub pos: usize,
+}
+
+impl Token {
+    /// True for a bare word matching `keyword`, ignoring case.
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+}
+
+pub fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        let kind = match ch {
+            c if c.is_whitespace() => continue,
+            ',' => TokenKind::Comma,
+            '*' => TokenKind::Star,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '=' => TokenKind::Eq,
+            '!' => match chars.next_if(|(_, c)| *c == '=') {
+                Some(_) => TokenKind::NotEq,
+                None => return Err(QueryError::new(query, pos, "expected '=' after '!'")),
+            },
+            '<' => match chars.next_if(|(_, c)| *c == '=' || *c == '>') {
+                Some((_, '=')) => TokenKind::LtEq,
+                Some(_) => TokenKind::NotEq,
+                None => TokenKind::Lt,
+            },
+            '>' => match chars.next_if(|(_, c)| *c == '=') {
+                Some(_) => TokenKind::GtEq,
+                None => TokenKind::Gt,
+            },
+            '\'' | '"' => {
+                let quote = ch;
+                let mut s = String::new();
+
+                loop {
+                    match chars.next() {
+                        // a doubled quote stands for the quote itself
+                        Some((_, c)) if c == quote => match chars.next_if(|(_, c)| *c == quote) {
+                            Some(_) => s.push(quote),
+                            None => break,
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => return Err(QueryError::new(query, pos, "unterminated quote")),
+                    }
+                }
+
+                if quote == '\'' { TokenKind::String(s) } else { TokenKind::QuotedIdent(s) }
+            }
+            c if c.is_ascii_digit() => {
+                let mut end = pos + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    end = i + c.len_utf8();
+                }
+
+                match query[pos..end].parse() {
+                    Ok(n) => TokenKind::Number(n),
+                    Err(_) => return Err(QueryError::new(query, pos, "number is too large")),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = pos + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    end = i + c.len_utf8();
+                }
+
+                TokenKind::Word(query[pos..end].to_owned())
+            }
+            c => return Err(QueryError::new(query, pos, &format!("unexpected character '{c}'"))),
+        };
+
+        tokens.push(Token { kind, pos });
+    }
+
+    tokens.push(Token { kind: TokenKind::End, pos: query.len() });
+
+    Ok(tokens)
+}
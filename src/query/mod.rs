@@ -0,0 +1,49 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{error::Result, parser::table::Table};
+
+use lexer::tokenize;
+use parser::Parser;
+use plan::Plan;
+
+pub mod lexer;
+pub mod parser;
+pub mod plan;
+
+/// A problem with a query, pointing at where in the query it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    pub query: String,
+    /// byte offset into `query`
+    pub pos: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    pub fn new(query: &str, pos: usize, message: &str) -> Self {
+        QueryError { query: query.to_owned(), pos, message: message.to_owned() }
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let column = self.query[..self.pos].chars().count();
+
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "    {}", self.query)?;
+        write!(f, "    {}^", " ".repeat(column))
+    }
+}
+
+/// Parses and checks `query` against `table`.
+pub fn compile(query: &str, table: &Table) -> Result<Plan> {
+    let tokens = tokenize(query)?;
+    let parsed = Parser::new(query, tokens).parse()?;
+
+    Ok(Plan::compile(query, &parsed, table)?)
+}
+
+/// Runs `query` over `table`, e.g. `SELECT song, artist WHERE msplayed > 30000 ORDER BY time DESC LIMIT 20`.
+pub fn run(query: &str, table: Table) -> Result<Table> {
+    compile(query, &table)?.execute(table)
+}
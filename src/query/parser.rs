@@ -0,0 +1,400 @@
+use super::{
+    lexer::{Token, TokenKind},
+    QueryError,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub pos: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(u64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+/// The text matchers, all ignoring case.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MatchOp {
+    Contains,
+    StartsWith,
+    EndsWith,
+    ILike,
+    Matches,
+}
+
+impl MatchOp {
+    fn from_keyword(word: &str) -> Option<Self> {
+        Some(match word.to_lowercase().as_str() {
+            "contains" => MatchOp::Contains,
+            "starts_with" => MatchOp::StartsWith,
+            "ends_with" => MatchOp::EndsWith,
+            "ilike" => MatchOp::ILike,
+            "matches" => MatchOp::Matches,
+            _ => return None,
+        })
+    }
+}
+
+/// A literal and where it starts in the query.
+pub type Value = (Literal, usize);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Compare { column: Ident, op: CompareOp, value: Value },
+    IsNull { column: Ident, negated: bool },
+    In { column: Ident, values: Vec<Value>, negated: bool },
+    Between { column: Ident, low: Value, high: Value },
+    Match { column: Ident, op: MatchOp, value: Value, negated: bool },
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+/// `expr [ASC|DESC] [NULLS FIRST|LAST]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderBy {
+    pub column: SelectExpr,
+    pub descending: bool,
+    /// `None` when the query doesn't say
+    pub nulls_first: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectExpr {
+    Column(Ident),
+    /// `func(column)`, or `func(*)` when `arg` is `None`. Either an aggregate
+    /// or a bucket like `year(time)`, which one is up to the planner.
+    Call { func: Ident, arg: Option<Ident> },
+}
+
+impl SelectExpr {
+    /// Where the expression starts in the query.
+    pub fn pos(&self) -> usize {
+        match self {
+            SelectExpr::Column(ident) => ident.pos,
+            SelectExpr::Call { func, .. } => func.pos,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectItem {
+    pub expr: SelectExpr,
+    pub alias: Option<Ident>,
+}
+
+/// `[SELECT cols] [WHERE cond] [GROUP BY cols] [ORDER BY col [ASC|DESC], ..] [LIMIT n]`,
+/// every clause optional.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Query {
+    /// `None` for `SELECT *` or no select clause at all
+    pub select: Option<Vec<SelectItem>>,
+    pub filter: Option<Condition>,
+    pub group_by: Option<Vec<SelectExpr>>,
+    pub order_by: Option<Vec<OrderBy>>,
+    pub limit: Option<usize>,
+}
+
+const KEYWORDS: [&str; 22] = [
+    "select", "where", "and", "or", "not", "is", "null", "in", "between", "contains", "starts_with", "ends_with", "ilike", "matches", "group",
+    "order", "by", "asc", "desc", "nulls", "limit", "as",
+];
+
+pub struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(query: &'a str, tokens: Vec<Token>) -> Self {
+        Parser { query, tokens, at: 0 }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.at]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.at].clone();
+        if token.kind != TokenKind::End {
+            self.at += 1;
+        }
+        token
+    }
+
+    fn error(&self, pos: usize, msg: &str) -> QueryError {
+        QueryError::new(self.query, pos, msg)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_keyword(keyword) {
+            self.next();
+            return true;
+        }
+        false
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), QueryError> {
+        if self.eat_keyword(keyword) {
+            return Ok(());
+        }
+        Err(self.error(self.peek().pos, &format!("expected {}", keyword.to_uppercase())))
+    }
+
+    fn ident(&mut self) -> Result<Ident, QueryError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Word(w) if !KEYWORDS.contains(&w.to_lowercase().as_str()) => Ok(Ident { name: w, pos: token.pos }),
+            TokenKind::QuotedIdent(w) => Ok(Ident { name: w, pos: token.pos }),
+            _ => Err(self.error(token.pos, "expected a column name")),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, QueryError> {
+        let token = self.next();
+        let lit = match token.kind {
+            TokenKind::String(s) => Literal::String(s),
+            TokenKind::Number(n) => Literal::Number(n),
+            TokenKind::Word(w) if w.eq_ignore_ascii_case("true") => Literal::Bool(true),
+            TokenKind::Word(w) if w.eq_ignore_ascii_case("false") => Literal::Bool(false),
+            TokenKind::Word(w) if w.eq_ignore_ascii_case("null") => Literal::Null,
+            _ => return Err(self.error(token.pos, "expected a value like 'text', 42, true or null")),
+        };
+        Ok((lit, token.pos))
+    }
+
+    fn primary(&mut self) -> Result<Condition, QueryError> {
+        if self.peek().kind == TokenKind::LParen {
+            let open = self.next();
+            let cond = self.condition()?;
+            if self.next().kind != TokenKind::RParen {
+                return Err(self.error(open.pos, "unclosed parenthesis"));
+            }
+            return Ok(cond);
+        }
+
+        let column = self.ident()?;
+
+        if self.eat_keyword("is") {
+            let negated = self.eat_keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Condition::IsNull { column, negated });
+        }
+
+        let negated = self.eat_keyword("not");
+
+        let match_op = match &self.peek().kind {
+            TokenKind::Word(w) => MatchOp::from_keyword(w),
+            _ => None,
+        };
+        if let Some(op) = match_op {
+            self.next();
+            let value = self.literal()?;
+            return Ok(Condition::Match { column, op, value, negated });
+        }
+
+        if negated && !self.peek().is_keyword("in") {
+            return Err(self.error(self.peek().pos, "expected IN, CONTAINS, STARTS_WITH, ENDS_WITH, ILIKE or MATCHES"));
+        }
+
+        if self.eat_keyword("in") {
+            let open = self.next();
+            if open.kind != TokenKind::LParen {
+                return Err(self.error(open.pos, "expected '(' and a list of values"));
+            }
+
+            let mut values = vec![self.literal()?];
+            while self.peek().kind == TokenKind::Comma {
+                self.next();
+                values.push(self.literal()?);
+            }
+
+            let close = self.next();
+            if close.kind != TokenKind::RParen {
+                return Err(self.error(close.pos, "expected ',' or ')'"));
+            }
+
+            return Ok(Condition::In { column, values, negated });
+        }
+
+        if self.eat_keyword("between") {
+            let low = self.literal()?;
+            self.expect_keyword("and")?;
+            let high = self.literal()?;
+            return Ok(Condition::Between { column, low, high });
+        }
+
+        let op_token = self.next();
+        let op = match op_token.kind {
+            TokenKind::Eq => CompareOp::Eq,
+            TokenKind::NotEq => CompareOp::NotEq,
+            TokenKind::Lt => CompareOp::Lt,
+            TokenKind::LtEq => CompareOp::LtEq,
+            TokenKind::Gt => CompareOp::Gt,
+            TokenKind::GtEq => CompareOp::GtEq,
+            _ => return Err(self.error(op_token.pos, "expected a comparison like =, <, >=, IN, BETWEEN, CONTAINS, ILIKE, MATCHES or IS NULL")),
+        };
+
+        let value = self.literal()?;
+
+        Ok(Condition::Compare { column, op, value })
+    }
+
+    fn unary(&mut self) -> Result<Condition, QueryError> {
+        if self.eat_keyword("not") {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+
+        self.primary()
+    }
+
+    fn conjunction(&mut self) -> Result<Condition, QueryError> {
+        let mut cond = self.unary()?;
+
+        while self.eat_keyword("and") {
+            cond = Condition::And(Box::new(cond), Box::new(self.unary()?));
+        }
+
+        Ok(cond)
+    }
+
+    /// OR binds looser than AND, which binds looser than NOT.
+    fn condition(&mut self) -> Result<Condition, QueryError> {
+        let mut cond = self.conjunction()?;
+
+        while self.eat_keyword("or") {
+            cond = Condition::Or(Box::new(cond), Box::new(self.conjunction()?));
+        }
+
+        Ok(cond)
+    }
+
+    fn select_expr(&mut self) -> Result<SelectExpr, QueryError> {
+        let ident = self.ident()?;
+
+        if self.peek().kind != TokenKind::LParen {
+            return Ok(SelectExpr::Column(ident));
+        }
+        self.next();
+
+        let arg = match self.peek().kind {
+            TokenKind::Star => {
+                self.next();
+                None
+            }
+            _ => Some(self.ident()?),
+        };
+
+        let close = self.next();
+        if close.kind != TokenKind::RParen {
+            return Err(self.error(close.pos, "expected ')'"));
+        }
+
+        Ok(SelectExpr::Call { func: ident, arg })
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem, QueryError> {
+        let expr = self.select_expr()?;
+        let alias = match self.eat_keyword("as") {
+            true => Some(self.ident()?),
+            false => None,
+        };
+
+        Ok(SelectItem { expr, alias })
+    }
+
+    fn order_item(&mut self) -> Result<OrderBy, QueryError> {
+        let column = self.select_expr()?;
+        let descending = if self.eat_keyword("desc") {
+            true
+        } else {
+            self.eat_keyword("asc");
+            false
+        };
+
+        let nulls_first = match self.eat_keyword("nulls") {
+            true if self.eat_keyword("first") => Some(true),
+            true if self.eat_keyword("last") => Some(false),
+            true => return Err(self.error(self.peek().pos, "expected FIRST or LAST")),
+            false => None,
+        };
+
+        Ok(OrderBy { column, descending, nulls_first })
+    }
+
+    fn select_expr_list(&mut self) -> Result<Vec<SelectExpr>, QueryError> {
+        let mut exprs = vec![self.select_expr()?];
+        while self.peek().kind == TokenKind::Comma {
+            self.next();
+            exprs.push(self.select_expr()?);
+        }
+        Ok(exprs)
+    }
+
+    pub fn parse(mut self) -> Result<Query, QueryError> {
+        let mut query = Query::default();
+
+        if self.eat_keyword("select") {
+            if self.peek().kind == TokenKind::Star {
+                self.next();
+            } else {
+                let mut items = vec![self.select_item()?];
+                while self.peek().kind == TokenKind::Comma {
+                    self.next();
+                    items.push(self.select_item()?);
+                }
+                query.select = Some(items);
+            }
+        }
+
+        if self.eat_keyword("where") {
+            query.filter = Some(self.condition()?);
+        }
+
+        if self.eat_keyword("group") {
+            self.expect_keyword("by")?;
+            query.group_by = Some(self.select_expr_list()?);
+        }
+
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            let mut order = vec![self.order_item()?];
+            while self.peek().kind == TokenKind::Comma {
+                self.next();
+                order.push(self.order_item()?);
+            }
+            query.order_by = Some(order);
+        }
+
+        if self.eat_keyword("limit") {
+            let token = self.next();
+            match token.kind {
+                TokenKind::Number(n) => query.limit = Some(n as usize),
+                _ => return Err(self.error(token.pos, "expected the number of rows")),
+            }
+        }
+
+        let token = self.peek();
+        if token.kind != TokenKind::End {
+            return Err(self.error(token.pos, "unexpected input, expected WHERE, GROUP BY, ORDER BY, LIMIT or the end of the query"));
+        }
+
+        Ok(query)
+    }
+}
@@ -0,0 +1,424 @@
+use crate::{
+    error::Result,
+    parser::{
+        aggregate::Aggregate,
+        column::Values,
+        expr::{Bucket, Expr},
+        parse::{DateTime, FieldKind, BIG_HISTORY_SCHEMA},
+        predicate::Predicate,
+        regex::Regex,
+        table::{Field, SortKey, Table},
+    },
+};
+
+use super::{
+    parser::{CompareOp, Condition, Ident, Literal, MatchOp, Query, SelectExpr, Value},
+    QueryError,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub keys: Vec<String>,
+    pub aggs: Vec<Aggregate>,
+}
+
+/// A checked query, ready to run against the table it was compiled for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub filter: Option<Predicate>,
+    /// columns added before grouping, named like the expression
+    pub computed: Vec<Expr>,
+    pub group: Option<Group>,
+    /// output column and the name it is shown under
+    pub columns: Option<Vec<(String, String)>>,
+    pub order_by: Option<Vec<SortKey>>,
+    pub limit: Option<usize>,
+}
+
+/// `yyyy-mm-dd`, `yyyy-mm-dd hh:mm` or `yyyy-mm-dd hh:mm:ss`
+fn parse_date(s: &str) -> Option<DateTime> {
+    if s.contains(' ') {
+        return s.parse().ok();
+    }
+
+    let mut parts = s.split('-');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(y), Some(m), Some(d), None) => DateTime::new(y.parse().ok()?, m.parse().ok()?, d.parse().ok()?, 0, 0, 0).ok(),
+        _ => None,
+    }
+}
+
+/// What a `SelectExpr` turned out to be.
+enum Resolved {
+    Column(String),
+    Computed(Expr),
+    Aggregate(Aggregate),
+}
+
+struct Compiler<'a> {
+    query: &'a str,
+    table: &'a Table,
+}
+
+impl Compiler<'_> {
+    fn column(&self, ident: &Ident) -> std::result::Result<usize, QueryError> {
+        self.table
+            .get_col(&ident.name)
+            .map_err(|_| QueryError::new(self.query, ident.pos, &format!("no such column '{}'", ident.name)))
+    }
+
+    fn resolve(&self, expr: &SelectExpr) -> std::result::Result<Resolved, QueryError> {
+        let (func, arg) = match expr {
+            SelectExpr::Column(ident) => {
+                self.column(ident)?;
+                return Ok(Resolved::Column(ident.name.clone()));
+            }
+            SelectExpr::Call { func, arg } => (func, arg),
+        };
+
+        if let Some(arg) = arg {
+            self.column(arg)?;
+        }
+
+        if let Some(bucket) = Bucket::from_name(&func.name) {
+            let arg = arg.as_ref().ok_or_else(|| QueryError::new(self.query, func.pos, &format!("{} expects a column", bucket.name())))?;
+            return Ok(Resolved::Computed(Expr::bucket(bucket, &arg.name)));
+        }
+
+        Aggregate::from_name(&func.name, arg.as_ref().map(|a| a.name.as_str())).map(Resolved::Aggregate).ok_or_else(|| {
+            QueryError::new(
+                self.query,
+                func.pos,
+                "expected count(*), sum(col), avg(col), min(col), max(col), count_distinct(col) or a bucket like year(col), month, weekday, hour, date or week",
+            )
+        })
+    }
+
+    /// Name `expr` has in the output. Anything but aggregates has to be a group key once grouping.
+    fn output_name(&self, expr: &SelectExpr, group: &Option<Group>) -> std::result::Result<String, QueryError> {
+        let name = match self.resolve(expr)? {
+            Resolved::Aggregate(agg) => return Ok(agg.to_string()),
+            Resolved::Column(name) => name,
+            Resolved::Computed(expr) => expr.to_string(),
+        };
+
+        match group {
+            Some(group) if !group.keys.contains(&name) => Err(QueryError::new(
+                self.query,
+                expr.pos(),
+                &format!("'{}' has to be in GROUP BY or inside an aggregate", name),
+            )),
+            _ => Ok(name),
+        }
+    }
+
+    /// Kind of value `column` holds: the kind of its vector, or for one that
+    /// has no typed values the kind the schema gives it. `None` for mixed
+    /// columns, which take any literal.
+    fn kind(&self, column: &Ident) -> std::result::Result<Option<FieldKind>, QueryError> {
+        Ok(match self.table.column(self.column(column)?).values() {
+            Values::Number(_) => Some(FieldKind::Number),
+            Values::Bool(_) => Some(FieldKind::Bool),
+            Values::Date(_) => Some(FieldKind::Date),
+            Values::String(_) | Values::Symbols(_) => Some(FieldKind::String),
+            Values::Empty | Values::Mixed(_) => BIG_HISTORY_SCHEMA.kind_of(&column.name),
+        })
+    }
+
+    /// Turns a literal into the field type stored in `column`, so `'2020-01-01'`
+    /// compares as a date against `time`. Literals of another type than the
+    /// column's are an error, they'd compare by type rather than value.
+    fn value(&self, column: &Ident, (lit, pos): &Value) -> std::result::Result<Field, QueryError> {
+        let expected = |what: &str| Err(QueryError::new(self.query, *pos, what));
+
+        match (lit, self.kind(column)?) {
+            (Literal::Null, _) => Ok(Field::Null),
+            (Literal::Number(n), None | Some(FieldKind::Number)) => Ok(Field::Number(*n)),
+            (Literal::Bool(b), None | Some(FieldKind::Bool)) => Ok(Field::Bool(*b)),
+            (Literal::String(s), Some(FieldKind::Date)) => Ok(Field::Date(
+                parse_date(s).ok_or_else(|| QueryError::new(self.query, *pos, "expected a date like '2020-01-31' or '2020-01-31 13:00'"))?,
+            )),
+            (Literal::String(s), None | Some(FieldKind::String)) => Ok(Field::String(s.clone())),
+            (_, Some(FieldKind::Number)) => expected("expected a number"),
+            (_, Some(FieldKind::Bool)) => expected("expected true or false"),
+            (_, Some(FieldKind::Date)) => expected("expected a date in quotes, like '2020-01-31'"),
+            (_, Some(FieldKind::String)) => expected("expected text in quotes"),
+        }
+    }
+
+    fn condition(&self, cond: &Condition) -> std::result::Result<Predicate, QueryError> {
+        Ok(match cond {
+            Condition::And(a, b) => self.condition(a)?.and(self.condition(b)?),
+            Condition::Or(a, b) => self.condition(a)?.or(self.condition(b)?),
+            Condition::Not(c) => self.condition(c)?.not(),
+            Condition::IsNull { column, negated } => {
+                self.column(column)?;
+                let name = column.name.clone();
+                if *negated { Predicate::IsNotNull(name) } else { Predicate::IsNull(name) }
+            }
+            Condition::In { column, values, negated } => {
+                self.column(column)?;
+                let values = values.iter().map(|v| self.value(column, v)).collect::<std::result::Result<_, _>>()?;
+                let p = Predicate::In(column.name.clone(), values);
+                if *negated { p.not() } else { p }
+            }
+            Condition::Between { column, low, high } => {
+                self.column(column)?;
+                let name = column.name.clone();
+                Predicate::GtEq(name.clone(), self.value(column, low)?).and(Predicate::LtEq(name, self.value(column, high)?))
+            }
+            Condition::Match { column, op, value: (lit, pos), negated } => {
+                self.column(column)?;
+                let Literal::String(s) = lit else {
+                    return Err(QueryError::new(self.query, *pos, "expected text in quotes"));
+                };
+
+                let name = column.name.clone();
+                let p = match op {
+                    MatchOp::Contains => Predicate::Contains(name, s.clone()),
+                    MatchOp::StartsWith => Predicate::StartsWith(name, s.clone()),
+                    MatchOp::EndsWith => Predicate::EndsWith(name, s.clone()),
+                    MatchOp::ILike => Predicate::ILike(name, s.clone()),
+                    MatchOp::Matches => {
+                        Regex::new(s, true).map_err(|e| QueryError::new(self.query, *pos, &format!("invalid pattern: {e}")))?;
+                        Predicate::Matches(name, s.clone())
+                    }
+                };
+                if *negated { p.not() } else { p }
+            }
+            Condition::Compare { column, op, value } => {
+                self.column(column)?;
+                let name = column.name.clone();
+                let value = self.value(column, value)?;
+
+                match op {
+                    CompareOp::Eq => Predicate::Eq(name, value),
+                    CompareOp::NotEq => Predicate::NotEq(name, value),
+                    CompareOp::Gt => Predicate::Gt(name, value),
+                    CompareOp::GtEq => Predicate::GtEq(name, value),
+                    CompareOp::Lt => Predicate::Lt(name, value),
+                    CompareOp::LtEq => Predicate::LtEq(name, value),
+                }
+            }
+        })
+    }
+}
+
+impl Plan {
+    pub fn compile(query: &str, parsed: &Query, table: &Table) -> std::result::Result<Self, QueryError> {
+        let compiler = Compiler { query, table };
+
+        let filter = match &parsed.filter {
+            Some(cond) => Some(compiler.condition(cond)?),
+            None => None,
+        };
+
+        let items = parsed.select.as_deref().unwrap_or_default();
+        let keys = parsed.group_by.as_deref().unwrap_or_default();
+        // an alias names something selected, which is resolved as that
+        let aliases: Vec<&str> = items.iter().filter_map(|i| i.alias.as_ref()).map(|a| a.name.as_str()).collect();
+        let order = parsed
+            .order_by
+            .iter()
+            .flatten()
+            .map(|o| &o.column)
+            .filter(|expr| !matches!(expr, SelectExpr::Column(ident) if aliases.contains(&ident.name.as_str())));
+
+        // aggregates anywhere group the table, over nothing if there's no GROUP BY
+        let mut aggs: Vec<Aggregate> = Vec::new();
+        let mut computed: Vec<Expr> = Vec::new();
+        for expr in items.iter().map(|i| &i.expr).chain(keys).chain(order) {
+            match compiler.resolve(expr)? {
+                Resolved::Aggregate(agg) if !aggs.contains(&agg) => aggs.push(agg),
+                Resolved::Computed(expr) if !computed.contains(&expr) => computed.push(expr),
+                _ => {}
+            }
+        }
+
+        let group = match &parsed.group_by {
+            Some(keys) => Some(Group {
+                keys: keys
+                    .iter()
+                    .map(|k| match compiler.resolve(k)? {
+                        Resolved::Column(name) => Ok(name),
+                        Resolved::Computed(expr) => Ok(expr.to_string()),
+                        Resolved::Aggregate(_) => Err(QueryError::new(query, k.pos(), "can't GROUP BY an aggregate")),
+                    })
+                    .collect::<std::result::Result<_, _>>()?,
+                aggs,
+            }),
+            None if !aggs.is_empty() => Some(Group { keys: Vec::new(), aggs }),
+            None => None,
+        };
+
+        let columns = match &parsed.select {
+            Some(items) => Some(
+                items
+                    .iter()
+                    .map(|item| {
+                        let name = compiler.output_name(&item.expr, &group)?;
+                        let shown = item.alias.as_ref().map_or_else(|| name.clone(), |a| a.name.clone());
+                        Ok((name, shown))
+                    })
+                    .collect::<std::result::Result<Vec<_>, QueryError>>()?,
+            ),
+            None => None,
+        };
+
+        let order_by = match &parsed.order_by {
+            Some(order) => Some(
+                order
+                    .iter()
+                    .map(|order| {
+                        // ordering by an alias orders by what it names
+                        let aliased = match &order.column {
+                            SelectExpr::Column(ident) => columns.iter().flatten().find(|(_, shown)| *shown == ident.name),
+                            _ => None,
+                        };
+
+                        let name = match aliased {
+                            Some((name, _)) => name.clone(),
+                            None => compiler.output_name(&order.column, &group)?,
+                        };
+
+                        let key = if order.descending { SortKey::desc(&name) } else { SortKey::asc(&name) };
+                        Ok(match order.nulls_first {
+                            Some(true) => key.nulls_first(),
+                            _ => key,
+                        })
+                    })
+                    .collect::<std::result::Result<Vec<_>, QueryError>>()?,
+            ),
+            None => None,
+        };
+
+        Ok(Plan { filter, computed, group, columns, order_by, limit: parsed.limit })
+    }
+
+    /// Text the filter wants a column to be exactly, from `col = '..'` and
+    /// `col IN (..)` that aren't negated.
+    pub fn exact_matches(&self) -> Vec<(&str, &str)> {
+        fn walk<'a>(p: &'a Predicate, out: &mut Vec<(&'a str, &'a str)>) {
+            match p {
+                Predicate::Eq(col, Field::String(s)) => out.push((col, s)),
+                Predicate::In(col, values) => out.extend(values.iter().filter_map(|v| match v {
+                    Field::String(s) => Some((col.as_str(), s.as_str())),
+                    _ => None,
+                })),
+                Predicate::And(ps) | Predicate::Or(ps) => ps.iter().for_each(|p| walk(p, out)),
+                _ => {}
+            }
+        }
+
+        let mut out = Vec::new();
+        if let Some(filter) = &self.filter {
+            walk(filter, &mut out);
+        }
+        out
+    }
+
+    pub fn execute(&self, mut tbl: Table) -> Result<Table> {
+        if let Some(filter) = &self.filter {
+            tbl = tbl.filter(filter)?;
+        }
+
+        for expr in &self.computed {
+            tbl = tbl.with_column(&expr.to_string(), expr)?;
+        }
+
+        if let Some(group) = &self.group {
+            let keys: Vec<&str> = group.keys.iter().map(String::as_str).collect();
+            tbl = tbl.group_by(&keys, &group.aggs)?;
+        }
+
+        if let Some(keys) = &self.order_by {
+            tbl = tbl.sort_by(keys)?;
+        }
+
+        if let Some(limit) = self.limit {
+            tbl = tbl.limit(limit);
+        }
+
+        if let Some(cols) = &self.columns {
+            let cols: Vec<(&str, &str)> = cols.iter().map(|(name, shown)| (name.as_str(), shown.as_str())).collect();
+            tbl = tbl.select_as(&cols);
+        }
+
+        Ok(tbl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ErrorKind,
+        parser::table::{Field, Table},
+        query,
+    };
+
+    fn plays() -> Table {
+        let mut tbl = Table::new(["artist", "msplayed"]);
+        for (artist, ms) in [("a", 10), ("b", 30), ("a", 5), ("c", 20)] {
+            tbl.insert([Field::String(artist.to_owned()), Field::Number(ms)]).unwrap();
+        }
+        tbl
+    }
+
+    fn column(tbl: &Table, name: &str) -> Vec<Field> {
+        let col = tbl.get_col(name).unwrap();
+        (0..tbl.len()).map(|row| tbl.get(row, col)).collect()
+    }
+
+    #[test]
+    fn orders_by_select_alias() {
+        // the example in USAGE
+        let res = query::run("SELECT artist, sum(msplayed) AS total GROUP BY artist ORDER BY total DESC", plays()).unwrap();
+
+        let artists: Vec<String> = column(&res, "artist").iter().map(Field::to_string).collect();
+        assert_eq!(artists, ["b", "c", "a"]);
+        assert_eq!(column(&res, "total"), [Field::Number(30), Field::Number(20), Field::Number(15)]);
+    }
+
+    #[test]
+    fn aliases_can_swap_names() {
+        let res = query::run("SELECT artist AS msplayed, msplayed AS artist LIMIT 2", plays()).unwrap();
+
+        assert_eq!(res.header.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["msplayed", "artist"]);
+        assert_eq!(column(&res, "msplayed"), [Field::String("a".to_owned()), Field::String("b".to_owned())]);
+        assert_eq!(column(&res, "artist"), [Field::Number(10), Field::Number(30)]);
+    }
+
+    #[test]
+    fn alias_can_take_another_selected_name() {
+        let res = query::run("SELECT artist, msplayed AS artist LIMIT 1", plays()).unwrap();
+
+        let row: Vec<Field> = (0..res.header.len()).map(|i| res.get(0, res.header[i].1)).collect();
+        assert_eq!(res.header.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["artist", "artist"]);
+        assert_eq!(row, [Field::String("a".to_owned()), Field::Number(10)]);
+    }
+
+    #[test]
+    fn unknown_order_column_is_an_error() {
+        assert!(query::compile("SELECT artist AS a ORDER BY nope", &plays()).is_err());
+    }
+
+    #[test]
+    fn literal_of_wrong_type_is_an_error() {
+        assert!(query::compile("SELECT * WHERE msplayed > 'abc'", &plays()).is_err());
+        assert!(query::compile("SELECT * WHERE artist = 12", &plays()).is_err());
+        assert!(query::compile("SELECT * WHERE msplayed > 12", &plays()).is_ok());
+    }
+
+    #[test]
+    fn literal_type_comes_from_the_schema_without_values() {
+        let mut tbl = Table::new(["song", "artist", "shuffle", "msplayed"]);
+        tbl.insert([Field::String("s".to_owned()), Field::Null, Field::Null, Field::Null]).unwrap();
+
+        for query in ["SELECT song WHERE shuffle = 'abc'", "SELECT song WHERE artist > 5", "SELECT song WHERE msplayed = 'x'"] {
+            let err = query::compile(query, &tbl).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::Query(e) if e.pos == query.rfind(' ').unwrap() + 1), "{query}");
+            assert!(query::compile(query, &Table::new(["song", "artist", "shuffle", "msplayed"])).is_err());
+        }
+        assert!(query::compile("SELECT song WHERE shuffle = true AND artist = 'a' AND msplayed > 5", &tbl).is_ok());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    process::{Child, Command, Stdio},
+};
+
+use crate::parser::{
+    format::FieldFormat,
+    table::{Field, Table},
+};
+
+/// How `render` draws a table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    /// box drawing lines around every cell
+    pub borders: bool,
+    /// bold headers and dim nulls
+    pub color: bool,
+    /// columns are shrunk to fit this many terminal columns
+    pub max_width: Option<usize>,
+}
+
+/// Narrowest a column is shrunk to, one char and the ellipsis with room to spare
+const MIN_COLUMN: usize = 3;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Terminal columns `c` takes up: 0 for combining marks and controls, 2 for
+/// wide CJK and emoji, 1 for everything else.
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..=0x1f | 0x7f..=0x9f => 0,
+        0x300..=0x36f | 0x200b..=0x200f | 0x20d0..=0x20ff | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f680..=0x1f6ff
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+pub fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// `s` cut to at most `width` columns, ending in `…` when anything was cut.
+pub fn truncate(s: &str, width: usize) -> String {
+    if display_width(s) <= width {
+        return s.to_owned();
+    }
+
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = char_width(c);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+
+    out.push('…');
+    out
+}
+
+struct Cell {
+    text: String,
+    right: bool,
+    null: bool,
+}
+
+impl Cell {
+    fn new(field: &Field, format: &FieldFormat) -> Self {
+        // a newline or tab in a song name would break the grid
+        let text = field.format(format).chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+        Cell { text, right: matches!(field, Field::Number(_)), null: field.is_null() }
+    }
+}
+
+/// Shrinks the widest column a char at a time until the table fits `available`
+/// or nothing can shrink further.
+fn fit(widths: &mut [usize], available: usize) {
+    while widths.iter().sum::<usize>() > available {
+        match widths.iter_mut().filter(|w| **w > MIN_COLUMN).max_by_key(|w| **w) {
+            Some(widest) => *widest -= 1,
+            None => return,
+        }
+    }
+}
+
+fn line<W: Write>(out: &mut W, widths: &[usize], style: &Style, (left, fill, cross, right): (&str, &str, &str, &str)) -> io::Result<()> {
+    let segments: Vec<String> = widths.iter().map(|w| fill.repeat(w + if style.borders { 2 } else { 0 })).collect();
+    let sep = if style.borders { cross.to_owned() } else { "  ".to_owned() };
+
+    let (start, end) = if style.color { (DIM, RESET) } else { ("", "") };
+    match style.borders {
+        true => writeln!(out, "{start}{left}{}{right}{end}", segments.join(&sep)),
+        false => writeln!(out, "{start}{}{end}", segments.join(&sep)),
+    }
+}
+
+fn row<W: Write>(out: &mut W, cells: &[Cell], widths: &[usize], style: &Style, header: bool) -> io::Result<()> {
+    let texts: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| {
+            let text = truncate(&cell.text, *width);
+            let pad = " ".repeat(width - display_width(&text));
+            let padded = if cell.right { format!("{pad}{text}") } else { format!("{text}{pad}") };
+
+            match (style.color, header, cell.null) {
+                (true, true, _) => format!("{BOLD}{padded}{RESET}"),
+                (true, false, true) => format!("{DIM}{padded}{RESET}"),
+                _ => padded,
+            }
+        })
+        .collect();
+
+    match style.borders {
+        true => writeln!(out, "│ {} │", texts.join(" │ ")),
+        false => writeln!(out, "{}", texts.join("  ").trim_end()),
+    }
+}
+
+/// Writes `tbl` as an aligned grid with a header, each column shown by its
+/// entry in `formats`. Numbers are right aligned, text that doesn't fit is cut
+/// with an ellipsis.
+pub fn render<W: Write>(tbl: &Table, formats: &[FieldFormat], style: &Style, out: &mut W) -> io::Result<()> {
+    let header: Vec<Cell> = tbl
+        .header
+        .iter()
+        .map(|(name, _)| Cell { text: name.clone(), right: false, null: false })
+        .collect();
+    let rows: Vec<Vec<Cell>> = (0..tbl.len())
+        .map(|row| tbl.header.iter().zip(formats).map(|((_, col), format)| Cell::new(&tbl.get(row, *col), format)).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = header.iter().map(|c| display_width(&c.text)).collect();
+    for cells in &rows {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(display_width(&cell.text));
+        }
+    }
+
+    // headers of number columns line up with their numbers
+    let mut header = header;
+    if let Some(first) = rows.first() {
+        for (h, cell) in header.iter_mut().zip(first) {
+            h.right = cell.right;
+        }
+    }
+
+    if let Some(max_width) = style.max_width {
+        let overhead = match style.borders {
+            true => 3 * widths.len() + 1,
+            false => 2 * widths.len().saturating_sub(1),
+        };
+        fit(&mut widths, max_width.saturating_sub(overhead));
+    }
+
+    if style.borders {
+        line(out, &widths, style, ("┌", "─", "┬", "┐"))?;
+    }
+    row(out, &header, &widths, style, true)?;
+    line(out, &widths, style, ("├", "─", "┼", "┤"))?;
+
+    for cells in &rows {
+        row(out, cells, &widths, style, false)?;
+    }
+
+    if style.borders {
+        line(out, &widths, style, ("└", "─", "┴", "┘"))?;
+    }
+
+    Ok(())
+}
+
+/// Columns and rows of the controlling terminal. `$COLUMNS` and `$LINES` win,
+/// otherwise `stty` is asked.
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let env_size = |name: &str| env::var(name).ok().and_then(|v| v.parse::<usize>().ok());
+    if let (Some(cols), Some(lines)) = (env_size("COLUMNS"), env_size("LINES")) {
+        return Some((cols, lines));
+    }
+
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty").arg("size").stdin(tty).stderr(Stdio::null()).output().ok()?;
+    let size = String::from_utf8(output.stdout).ok()?;
+    let mut parts = size.split_whitespace().map(|p| p.parse::<usize>().ok());
+
+    let (lines, cols) = (parts.next()??, parts.next()??);
+    Some((env_size("COLUMNS").unwrap_or(cols), env_size("LINES").unwrap_or(lines)))
+}
+
+/// Starts `$PAGER`, or `less` letting colours through and not wrapping lines.
+/// `None` when no pager could be started.
+pub fn pager() -> Option<Child> {
+    let cmd = env::var("PAGER").ok().filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "less -FRSX".to_owned());
+    let mut parts = cmd.split_whitespace();
+
+    Command::new(parts.next()?).args(parts).stdin(Stdio::piped()).spawn().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(tbl: &Table, style: &Style) -> String {
+        let formats = vec![FieldFormat::Raw; tbl.header.len()];
+        let mut out = Vec::new();
+        render(tbl, &formats, style, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn plain(max_width: Option<usize>) -> Style {
+        Style { borders: false, color: false, max_width }
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('é'), 1);
+        assert_eq!(char_width('\u{301}'), 0);
+        assert_eq!(char_width('\u{200d}'), 0);
+        assert_eq!(char_width('\t'), 0);
+        assert_eq!(char_width('日'), 2);
+        assert_eq!(char_width('한'), 2);
+        assert_eq!(char_width('ア'), 2);
+        assert_eq!(char_width('Ａ'), 2);
+        assert_eq!(char_width('🎵'), 2);
+        assert_eq!(char_width('😀'), 2);
+        assert_eq!(char_width('🚀'), 2);
+
+        assert_eq!(display_width("Björk"), 5);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("宇多田ヒカル"), 12);
+        assert_eq!(display_width("Song 🎵"), 7);
+    }
+
+    #[test]
+    fn truncates_by_columns() {
+        assert_eq!(truncate("Tyler Childers", 14), "Tyler Childers");
+        assert_eq!(truncate("Tyler Childers", 13), "Tyler Childe…");
+        assert_eq!(truncate("Tyler Childers", 1), "…");
+        assert_eq!(truncate("", 0), "");
+
+        // a wide char that would cross the boundary is left out whole
+        assert_eq!(truncate("宇多田ヒカル", 12), "宇多田ヒカル");
+        assert_eq!(truncate("宇多田ヒカル", 11), "宇多田ヒカ…");
+        assert_eq!(truncate("宇多田ヒカル", 10), "宇多田ヒ…");
+        assert_eq!(truncate("宇多田ヒカル", 2), "…");
+        assert_eq!(truncate("🎵🎵🎵", 4), "🎵…");
+        assert_eq!(truncate("ab🎵", 3), "ab…");
+
+        for width in 1..14 {
+            assert!(display_width(&truncate("宇a多b田🎵ヒカル", width)) <= width, "{width}");
+        }
+    }
+
+    #[test]
+    fn fits_the_widest_first() {
+        let mut widths = vec![10, 4, 6];
+        fit(&mut widths, 16);
+        assert_eq!(widths, [6, 4, 6]);
+
+        fit(&mut widths, 12);
+        assert_eq!(widths, [4, 4, 4]);
+
+        // less room than the narrowest columns stops at them
+        fit(&mut widths, 2);
+        assert_eq!(widths, [MIN_COLUMN; 3]);
+
+        let mut widths = vec![2, 1];
+        fit(&mut widths, 0);
+        assert_eq!(widths, [2, 1]);
+    }
+
+    #[test]
+    fn aligns_wide_text() {
+        let mut tbl = Table::new(["artist", "msplayed"]);
+        tbl.insert([Field::String("宇多田ヒカル".into()), Field::Number(5)]).unwrap();
+        tbl.insert([Field::String("Björk".into()), Field::Number(12345)]).unwrap();
+
+        assert_eq!(
+            draw(&tbl, &plain(None)),
+            "artist        msplayed\n────────────  ────────\n宇多田ヒカル         5\nBjörk            12345\n"
+        );
+    }
+
+    #[test]
+    fn narrow_terminals_cut_headers_too() {
+        let mut tbl = Table::new(["episode_show_name", "count_distinct(song)"]);
+        tbl.insert([Field::String("宇多田ヒカル".into()), Field::Number(5)]).unwrap();
+
+        let text = draw(&tbl, &plain(Some(20)));
+        assert_eq!(text, "episode_…  count_di…\n─────────  ─────────\n宇多田ヒ…          5\n");
+        assert!(text.lines().all(|line| display_width(line) <= 20), "{text}");
+
+        // with less room than the narrowest columns lines run over
+        let text = draw(&tbl, &Style { borders: true, color: false, max_width: Some(5) });
+        assert_eq!(text, "┌─────┬─────┐\n│ ep… │ co… │\n├─────┼─────┤\n│ 宇… │   5 │\n└─────┴─────┘\n");
+    }
+}
@@ -0,0 +1,388 @@
+use std::io::{self, BufRead, Read};
+
+// Streaming DEFLATE (RFC 1951) decoder. Codes are decoded bit by bit the way
+// zlib's `puff` does it, which is plenty fast for JSON sized entries.
+
+const MAX_BITS: usize = 15;
+const WINDOW_SIZE: usize = 1 << 15;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// order the code length code lengths are stored in
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("deflate: {}", msg))
+}
+
+/// Canonical huffman code, stored as the number of codes per length and the
+/// symbols ordered by code.
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+
+        // a code may be incomplete (a single distance code is legal) but never over-subscribed
+        let mut left: i32 = 1;
+        for &c in &count[1..] {
+            left = (left << 1) - c as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed code"));
+            }
+        }
+
+        let mut offs = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offs[len + 1] = offs[len] + count[len];
+        }
+
+        let mut symbol = vec![0; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offs[len as usize] as usize] = sym as u16;
+                offs[len as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { count, symbol })
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+
+        let lit = Huffman::new(&lengths).expect("fixed literal code is valid");
+        let dist = Huffman::new(&[5; 30]).expect("fixed distance code is valid");
+
+        (lit, dist)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Block {
+    Header,
+    Stored(usize),
+    Codes,
+    Done,
+}
+
+pub struct Inflate<R> {
+    inner: R,
+    bit_buf: u32,
+    bit_count: u32,
+    block: Block,
+    last: bool,
+    lit: Huffman,
+    dist: Huffman,
+    window: Box<[u8; WINDOW_SIZE]>,
+    /// total bytes produced so far
+    written: usize,
+    copy_len: usize,
+    copy_dist: usize,
+}
+
+impl<R: BufRead> Inflate<R> {
+    pub fn new(inner: R) -> Self {
+        let (lit, dist) = Huffman::fixed();
+
+        Inflate {
+            inner,
+            bit_buf: 0,
+            bit_count: 0,
+            block: Block::Header,
+            last: false,
+            lit,
+            dist,
+            window: Box::new([0; WINDOW_SIZE]),
+            written: 0,
+            copy_len: 0,
+            copy_dist: 0,
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let buf = self.inner.fill_buf()?;
+        let b = *buf.first().ok_or_else(|| invalid("unexpected end of stream"))?;
+        self.inner.consume(1);
+
+        Ok(b)
+    }
+
+    fn bits(&mut self, need: u32) -> io::Result<u32> {
+        while self.bit_count < need {
+            self.bit_buf |= (self.byte()? as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let val = self.bit_buf & ((1u32 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+
+        Ok(val)
+    }
+
+    fn decode(&mut self, dist: bool) -> io::Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as i32;
+            let count = if dist { self.dist.count[len] } else { self.lit.count[len] } as i32;
+
+            if code - count < first {
+                let table = if dist { &self.dist } else { &self.lit };
+                return Ok(table.symbol[(index + (code - first)) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("ran out of codes"))
+    }
+
+    fn read_dynamic_tables(&mut self) -> io::Result<()> {
+        let nlen = self.bits(5)? as usize + 257;
+        let ndist = self.bits(5)? as usize + 1;
+        let ncode = self.bits(4)? as usize + 4;
+
+        if nlen > 286 || ndist > 30 {
+            return Err(invalid("bad counts"));
+        }
+
+        let mut lengths = [0u8; 320];
+        for &idx in &CLEN_ORDER[..ncode] {
+            lengths[idx] = self.bits(3)? as u8;
+        }
+
+        // the code length code is decoded through the literal slot for now
+        self.lit = Huffman::new(&lengths[..19])?;
+
+        let mut idx = 0;
+        while idx < nlen + ndist {
+            let sym = self.decode(false)?;
+
+            if sym < 16 {
+                lengths[idx] = sym as u8;
+                idx += 1;
+                continue;
+            }
+
+            let (val, repeat) = match sym {
+                16 => {
+                    if idx == 0 {
+                        return Err(invalid("repeat with no first length"));
+                    }
+                    (lengths[idx - 1], 3 + self.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.bits(3)? as usize),
+                _ => (0, 11 + self.bits(7)? as usize),
+            };
+
+            if idx + repeat > nlen + ndist {
+                return Err(invalid("too many lengths"));
+            }
+
+            lengths[idx..idx + repeat].fill(val);
+            idx += repeat;
+        }
+
+        if lengths[256] == 0 {
+            return Err(invalid("no end-of-block code"));
+        }
+
+        self.lit = Huffman::new(&lengths[..nlen])?;
+        self.dist = Huffman::new(&lengths[nlen..nlen + ndist])?;
+
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        if self.last {
+            self.block = Block::Done;
+            return Ok(());
+        }
+
+        self.last = self.bits(1)? == 1;
+
+        self.block = match self.bits(2)? {
+            0 => {
+                // stored blocks start on a byte boundary
+                self.bit_buf = 0;
+                self.bit_count = 0;
+
+                let len = self.byte()? as usize | (self.byte()? as usize) << 8;
+                let nlen = self.byte()? as usize | (self.byte()? as usize) << 8;
+                if len != !nlen & 0xffff {
+                    return Err(invalid("stored block length mismatch"));
+                }
+
+                Block::Stored(len)
+            }
+            1 => {
+                (self.lit, self.dist) = Huffman::fixed();
+                Block::Codes
+            }
+            2 => {
+                self.read_dynamic_tables()?;
+                Block::Codes
+            }
+            _ => return Err(invalid("invalid block type")),
+        };
+
+        Ok(())
+    }
+
+    fn push(&mut self, b: u8) {
+        self.window[self.written & WINDOW_MASK] = b;
+        self.written += 1;
+    }
+}
+
+impl<R: BufRead> Read for Inflate<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+
+        while n < out.len() {
+            if self.copy_len > 0 {
+                let b = self.window[(self.written - self.copy_dist) & WINDOW_MASK];
+                self.push(b);
+                out[n] = b;
+                n += 1;
+                self.copy_len -= 1;
+                continue;
+            }
+
+            match self.block {
+                Block::Header => self.read_header()?,
+                Block::Stored(0) => self.block = Block::Header,
+                Block::Stored(remaining) => {
+                    let b = self.byte()?;
+                    self.push(b);
+                    out[n] = b;
+                    n += 1;
+                    self.block = Block::Stored(remaining - 1);
+                }
+                Block::Codes => {
+                    let sym = self.decode(false)? as usize;
+
+                    match sym {
+                        0..=255 => {
+                            self.push(sym as u8);
+                            out[n] = sym as u8;
+                            n += 1;
+                        }
+                        256 => self.block = Block::Header,
+                        _ => {
+                            let sym = sym - 257;
+                            if sym >= LENGTH_BASE.len() {
+                                return Err(invalid("invalid length symbol"));
+                            }
+                            let len = LENGTH_BASE[sym] as usize + self.bits(LENGTH_EXTRA[sym] as u32)? as usize;
+
+                            let sym = self.decode(true)? as usize;
+                            if sym >= DIST_BASE.len() {
+                                return Err(invalid("invalid distance symbol"));
+                            }
+                            let dist = DIST_BASE[sym] as usize + self.bits(DIST_EXTRA[sym] as u32)? as usize;
+
+                            if dist > self.written.min(WINDOW_SIZE) {
+                                return Err(invalid("distance too far back"));
+                            }
+
+                            self.copy_len = len;
+                            self.copy_dist = dist;
+                        }
+                    }
+                }
+                Block::Done => break,
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::Inflate;
+
+    fn inflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        Inflate::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn stored_blocks() {
+        // a block that isn't the last, then one that is: header, LEN, NLEN, data
+        let data = [0x00, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0x01, 0x02, 0x00, 0xfd, 0xff, b'd', b'e'];
+        assert_eq!(inflate(&data).unwrap(), b"abcde");
+    }
+
+    #[test]
+    fn stored_length_has_to_match_its_complement() {
+        let data = [0x01, 0x03, 0x00, 0x00, 0x00, b'a', b'b', b'c'];
+        assert!(inflate(&data).is_err());
+    }
+
+    #[test]
+    fn fixed_block() {
+        // zlib with Z_FIXED, the repeats are a back reference
+        let data = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        assert_eq!(inflate(&data).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        let data = [
+            0x85, 0xcb, 0xcb, 0x09, 0x80, 0x30, 0x10, 0x05, 0xc0, 0xbb, 0x55, 0x6c, 0x01, 0x22, 0x89, 0xf9, 0x97, 0x63, 0x60, 0xc5, 0x43, 0x30,
+            0xa0, 0x01, 0xdb, 0xb7, 0x80, 0x3c, 0x78, 0xe7, 0x61, 0x4a, 0x91, 0xda, 0xc7, 0x68, 0xfa, 0x4a, 0x3f, 0xa5, 0xaa, 0x3e, 0xd2, 0x6f,
+            0x19, 0x97, 0xca, 0x77, 0xb4, 0xb6, 0x4a, 0x99, 0x7c, 0x5b, 0x4a, 0x26, 0x27, 0x83, 0x93, 0xc8, 0x49, 0xe0, 0x44, 0x72, 0x22, 0x38,
+            0x81, 0x9c, 0x00, 0x8e, 0x27, 0xc7, 0x83, 0xe3, 0xc8, 0x71, 0xe0, 0xec, 0xe4, 0xec, 0xe0, 0x58, 0x72, 0x2c, 0x38, 0x86, 0x1c, 0x33,
+            0x9f, 0x1f,
+        ];
+        let text: String = (90..=99).rev().map(|i| format!("{i} bottles of beer on the wall, {i} bottles of beer.\n")).collect();
+
+        assert_eq!(inflate(&data).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn truncated_input() {
+        assert!(inflate(&[0xcb, 0x48, 0xcd, 0xc9]).is_err());
+        assert!(inflate(&[]).is_err());
+    }
+
+    #[test]
+    fn reserved_block_type() {
+        assert!(inflate(&[0x07]).is_err());
+    }
+}
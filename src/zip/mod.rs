@@ -0,0 +1,473 @@
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Take};
+
+use inflate::Inflate;
+
+pub mod inflate;
+
+const LOCAL_HEADER_SIG: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIG: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIR_SIG: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIR_SIG: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x07064b50;
+
+/// fixed part of the end of central directory record, the comment follows it
+const END_OF_CENTRAL_DIR_LEN: u64 = 22;
+const MAX_COMMENT_LEN: u64 = 0xffff;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("zip: {}", msg))
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn read_buf<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[derive(Clone, Debug)]
+pub struct ZipEntry {
+    /// path inside the archive, directories separated by '/'
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    method: u16,
+    flags: u16,
+    crc32: u32,
+    header_offset: u64,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// CRC-32 of the uncompressed data, as the archive states it.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// Last part of `name`.
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// Opens the entry in `reader`, which has to be the archive the entry came
+    /// from. The data is decompressed while it is read.
+    pub fn reader<R: Read + Seek>(&self, mut reader: R) -> io::Result<EntryReader<R>> {
+        if self.flags & 1 != 0 {
+            return Err(invalid(&format!("{} is encrypted", self.name)));
+        }
+
+        reader.seek(SeekFrom::Start(self.header_offset))?;
+        let header = read_buf(&mut reader, 30)?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIG {
+            return Err(invalid("bad local file header"));
+        }
+
+        // the local name and extra field may differ in length from the central ones
+        let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+        reader.seek(SeekFrom::Current(skip))?;
+
+        let data = BufReader::new(reader.take(self.compressed_size));
+
+        let data = match self.method {
+            METHOD_STORED => EntryData::Stored(data),
+            METHOD_DEFLATED => EntryData::Deflated(Inflate::new(data)),
+            method => return Err(invalid(&format!("{} uses unsupported compression method {}", self.name, method))),
+        };
+
+        Ok(EntryReader { data, crc: Crc32::new(), read: 0, expected_crc: self.crc32, size: self.size })
+    }
+}
+
+enum EntryData<R> {
+    Stored(BufReader<Take<R>>),
+    Deflated(Inflate<BufReader<Take<R>>>),
+}
+
+/// Reads the uncompressed content of one entry and checks its size and crc
+/// once the end is reached.
+pub struct EntryReader<R> {
+    data: EntryData<R>,
+    crc: Crc32,
+    read: u64,
+    expected_crc: u32,
+    size: u64,
+}
+
+impl<R: Read> Read for EntryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match &mut self.data {
+            EntryData::Stored(r) => r.read(buf)?,
+            EntryData::Deflated(r) => r.read(buf)?,
+        };
+
+        self.crc.update(&buf[..n]);
+        self.read += n as u64;
+
+        if n == 0 && !buf.is_empty() {
+            if self.read != self.size {
+                return Err(invalid("entry size mismatch"));
+            }
+            if self.crc.finish() != self.expected_crc {
+                return Err(invalid("entry crc mismatch"));
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+pub struct ZipArchive {
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipArchive {
+    /// Reads the central directory. Entry data is only touched once an entry is opened.
+    pub fn new<R: Read + Seek>(mut reader: R) -> io::Result<Self> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+
+        // the end record sits at the very end, followed only by a comment of up to 64KiB
+        let tail_len = file_len.min(END_OF_CENTRAL_DIR_LEN + MAX_COMMENT_LEN);
+        reader.seek(SeekFrom::Start(file_len - tail_len))?;
+        let tail = read_buf(&mut reader, tail_len as usize)?;
+        if tail.len() < END_OF_CENTRAL_DIR_LEN as usize {
+            return Err(invalid("not a zip file"));
+        }
+
+        let eocd = (0..=tail.len().saturating_sub(END_OF_CENTRAL_DIR_LEN as usize))
+            .rev()
+            .find(|&i| u32_at(&tail, i) == END_OF_CENTRAL_DIR_SIG)
+            .ok_or_else(|| invalid("no end of central directory record, not a zip file?"))?;
+
+        let mut count = u16_at(&tail, eocd + 10) as u64;
+        let mut cd_size = u32_at(&tail, eocd + 12) as u64;
+        let mut cd_offset = u32_at(&tail, eocd + 16) as u64;
+
+        // zip64 archives keep the real values in a record pointed to by a locator before the end record
+        if (count == 0xffff || cd_size == 0xffff_ffff || cd_offset == 0xffff_ffff) && eocd >= 20 {
+            let locator = eocd - 20;
+            if u32_at(&tail, locator) == ZIP64_LOCATOR_SIG {
+                let record_offset = u64_at(&tail, locator + 8);
+                if record_offset.checked_add(56).is_none_or(|end| end > file_len) {
+                    return Err(invalid("zip64 end of central directory record is past the end of the file"));
+                }
+                reader.seek(SeekFrom::Start(record_offset))?;
+                let record = read_buf(&mut reader, 56)?;
+                if u32_at(&record, 0) != ZIP64_END_OF_CENTRAL_DIR_SIG {
+                    return Err(invalid("bad zip64 end of central directory record"));
+                }
+
+                count = u64_at(&record, 32);
+                cd_size = u64_at(&record, 40);
+                cd_offset = u64_at(&record, 48);
+            }
+        }
+
+        if cd_offset.checked_add(cd_size).is_none_or(|end| end > file_len) {
+            return Err(invalid("central directory runs past the end of the file"));
+        }
+        // every entry takes at least its fixed 46 bytes
+        if count > cd_size / 46 {
+            return Err(invalid("central directory is too small for its entries"));
+        }
+
+        reader.seek(SeekFrom::Start(cd_offset))?;
+        let cd = read_buf(&mut reader, cd_size as usize)?;
+
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut at = 0;
+
+        for _ in 0..count {
+            if at + 46 > cd.len() || u32_at(&cd, at) != CENTRAL_HEADER_SIG {
+                return Err(invalid("bad central directory entry"));
+            }
+
+            let flags = u16_at(&cd, at + 8);
+            let name_len = u16_at(&cd, at + 28) as usize;
+            let extra_len = u16_at(&cd, at + 30) as usize;
+            let comment_len = u16_at(&cd, at + 32) as usize;
+
+            let name_start = at + 46;
+            let extra_start = name_start + name_len;
+            let next = extra_start + extra_len + comment_len;
+            if next > cd.len() {
+                return Err(invalid("central directory entry runs past the directory"));
+            }
+
+            let mut entry = ZipEntry {
+                name: String::from_utf8_lossy(&cd[name_start..extra_start]).into_owned(),
+                method: u16_at(&cd, at + 10),
+                flags,
+                crc32: u32_at(&cd, at + 16),
+                compressed_size: u32_at(&cd, at + 20) as u64,
+                size: u32_at(&cd, at + 24) as u64,
+                header_offset: u32_at(&cd, at + 42) as u64,
+            };
+
+            read_zip64_extra(&mut entry, &cd[extra_start..extra_start + extra_len]);
+
+            entries.push(entry);
+            at = next;
+        }
+
+        Ok(ZipArchive { entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+}
+
+/// Fills in the sizes and offset that didn't fit the 32 bit fields. Only the
+/// saturated fields are present in the extra field, in this order.
+fn read_zip64_extra(entry: &mut ZipEntry, mut extra: &[u8]) {
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let len = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+        let mut data = &extra[4..4 + len];
+
+        if id == 0x0001 {
+            for field in [&mut entry.size, &mut entry.compressed_size, &mut entry.header_offset] {
+                if *field == 0xffff_ffff && data.len() >= 8 {
+                    *field = u64_at(data, 0);
+                    data = &data[8..];
+                }
+            }
+        }
+
+        extra = &extra[4 + len..];
+    }
+}
+
+/// CRC-32 as used by zip (IEEE, reflected).
+pub struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        let mut table = [0u32; 256];
+        for (n, slot) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *slot = c;
+        }
+
+        Crc32 { table, value: 0xffff_ffff }
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        for &b in buf {
+            self.value = self.table[((self.value ^ b as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn put16(out: &mut Vec<u8>, n: u16) {
+        out.extend(n.to_le_bytes());
+    }
+
+    fn put32(out: &mut Vec<u8>, n: u32) {
+        out.extend(n.to_le_bytes());
+    }
+
+    fn put64(out: &mut Vec<u8>, n: u64) {
+        out.extend(n.to_le_bytes());
+    }
+
+    /// An archive with `files` stored uncompressed. With `zip64` every size
+    /// and offset is in zip64 records only, the way big archives have them.
+    fn archive(files: &[(&str, &[u8])], zip64: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+
+        for (name, data) in files {
+            let mut crc = Crc32::new();
+            crc.update(data);
+            let (crc, offset) = (crc.finish(), out.len() as u64);
+
+            put32(&mut out, LOCAL_HEADER_SIG);
+            put16(&mut out, 20);
+            put16(&mut out, 0);
+            put16(&mut out, METHOD_STORED);
+            put32(&mut out, 0);
+            put32(&mut out, crc);
+            put32(&mut out, data.len() as u32);
+            put32(&mut out, data.len() as u32);
+            put16(&mut out, name.len() as u16);
+            put16(&mut out, 0);
+            out.extend(name.as_bytes());
+            out.extend(*data);
+
+            let mut extra = Vec::new();
+            if zip64 {
+                put16(&mut extra, 0x0001);
+                put16(&mut extra, 24);
+                put64(&mut extra, data.len() as u64);
+                put64(&mut extra, data.len() as u64);
+                put64(&mut extra, offset);
+            }
+            let saturated = |n: u64| if zip64 { 0xffff_ffff } else { n as u32 };
+
+            put32(&mut central, CENTRAL_HEADER_SIG);
+            put16(&mut central, 20);
+            put16(&mut central, 20);
+            put16(&mut central, 0);
+            put16(&mut central, METHOD_STORED);
+            put32(&mut central, 0);
+            put32(&mut central, crc);
+            put32(&mut central, saturated(data.len() as u64));
+            put32(&mut central, saturated(data.len() as u64));
+            put16(&mut central, name.len() as u16);
+            put16(&mut central, extra.len() as u16);
+            put16(&mut central, 0);
+            put16(&mut central, 0);
+            put16(&mut central, 0);
+            put32(&mut central, 0);
+            put32(&mut central, saturated(offset));
+            central.extend(name.as_bytes());
+            central.extend(extra);
+        }
+
+        let (cd_offset, cd_size, count) = (out.len() as u64, central.len() as u64, files.len() as u64);
+        out.extend(central);
+
+        if zip64 {
+            let record = out.len() as u64;
+            put32(&mut out, ZIP64_END_OF_CENTRAL_DIR_SIG);
+            put64(&mut out, 44);
+            put16(&mut out, 45);
+            put16(&mut out, 45);
+            put32(&mut out, 0);
+            put32(&mut out, 0);
+            put64(&mut out, count);
+            put64(&mut out, count);
+            put64(&mut out, cd_size);
+            put64(&mut out, cd_offset);
+
+            put32(&mut out, ZIP64_LOCATOR_SIG);
+            put32(&mut out, 0);
+            put64(&mut out, record);
+            put32(&mut out, 1);
+        }
+
+        put32(&mut out, END_OF_CENTRAL_DIR_SIG);
+        put16(&mut out, 0);
+        put16(&mut out, 0);
+        put16(&mut out, if zip64 { 0xffff } else { count as u16 });
+        put16(&mut out, if zip64 { 0xffff } else { count as u16 });
+        put32(&mut out, if zip64 { 0xffff_ffff } else { cd_size as u32 });
+        put32(&mut out, if zip64 { 0xffff_ffff } else { cd_offset as u32 });
+        put16(&mut out, 0);
+
+        out
+    }
+
+    fn contents(data: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let archive = ZipArchive::new(Cursor::new(data))?;
+        archive
+            .entries()
+            .iter()
+            .map(|entry| {
+                let mut content = Vec::new();
+                entry.reader(Cursor::new(data))?.read_to_end(&mut content)?;
+                Ok((entry.name.clone(), content))
+            })
+            .collect()
+    }
+
+    const FILES: &[(&str, &[u8])] = &[("Spotify Account Data/", b""), ("Spotify Account Data/StreamingHistory0.json", b"[{\"msPlayed\": 1}]")];
+
+    #[test]
+    fn reads_entries() {
+        let read = contents(&archive(FILES, false)).unwrap();
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0], ("Spotify Account Data/".to_owned(), Vec::new()));
+        assert_eq!(read[1], (FILES[1].0.to_owned(), FILES[1].1.to_vec()));
+    }
+
+    #[test]
+    fn skips_a_trailing_comment() {
+        let mut data = archive(FILES, false);
+        let len = data.len();
+        data[len - 2..].copy_from_slice(&7u16.to_le_bytes());
+        data.extend(b"comment");
+
+        assert_eq!(contents(&data).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reads_zip64() {
+        let data = archive(FILES, true);
+        let archive = ZipArchive::new(Cursor::new(&data)).unwrap();
+
+        let entry = &archive.entries()[1];
+        assert_eq!((entry.size, entry.compressed_size), (FILES[1].1.len() as u64, FILES[1].1.len() as u64));
+        assert_eq!(contents(&data).unwrap()[1].1, FILES[1].1);
+    }
+
+    #[test]
+    fn rejects_short_files() {
+        for data in [&[][..], b"PK\x05", b"PK\x05\x06"] {
+            assert!(ZipArchive::new(Cursor::new(data)).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_a_directory_past_the_end() {
+        let mut data = archive(FILES, false);
+        let len = data.len();
+        data[len - 6..len - 2].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        assert!(ZipArchive::new(Cursor::new(&data)).is_err());
+
+        let mut data = archive(FILES, true);
+        let locator = data.len() - 22 - 20;
+        data[locator + 8..locator + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(ZipArchive::new(Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn checks_the_crc() {
+        let mut data = archive(FILES, false);
+        let at = data.windows(2).position(|w| w == b"[{").unwrap();
+        data[at] = b'(';
+
+        assert!(contents(&data).is_err());
+    }
+}